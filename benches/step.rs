@@ -0,0 +1,70 @@
+use barnes_hut::physics::benchmarks::{Integration, LeafSums};
+use barnes_hut::physics::{ForceStrategy, Universe};
+use barnes_hut::simulation::Model;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use nannou::geom::pt2;
+
+const NUM_PARTICLES: usize = 5000;
+const DT: f32 = 0.01;
+
+/// A step of the default universe, whose cost is mostly the tree walk, with each strategy for
+/// walking the tree and a range of leaf sizes for the leaf kernel to sum over.
+fn bench_step(c: &mut Criterion) {
+    let mut group = c.benchmark_group("step");
+    for force_strategy in [ForceStrategy::PerBody, ForceStrategy::GroupWalk] {
+        for leaf_capacity in [1, 8, 32] {
+            let mut universe = Universe::new(NUM_PARTICLES);
+            universe.force_strategy = force_strategy;
+            universe.leaf_capacity = leaf_capacity;
+            group.bench_with_input(
+                BenchmarkId::new(format!("{force_strategy:?}"), leaf_capacity),
+                &leaf_capacity,
+                |b, _| b.iter(|| universe.step(DT)),
+            );
+        }
+    }
+    group.finish();
+}
+
+/// Integrating the particles stored as an array of structs, as they used to be, against the
+/// struct of arrays.
+fn bench_integration(c: &mut Criterion) {
+    let mut group = c.benchmark_group("integration");
+    for count in [10_000, 1_000_000] {
+        let mut integration = Integration::new(count);
+        group.bench_with_input(
+            BenchmarkId::new("array of structs", count),
+            &count,
+            |b, _| b.iter(|| integration.step_rows(DT)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("struct of arrays", count),
+            &count,
+            |b, _| b.iter(|| integration.step_columns(DT)),
+        );
+    }
+    group.finish();
+}
+
+/// Summing an interaction list's field one `g_from` at a time, as the tree walk used to, against
+/// the leaf kernel.
+fn bench_leaf_sum(c: &mut Criterion) {
+    let mut group = c.benchmark_group("leaf sum");
+    for count in [8, 64, 4096] {
+        let sums = LeafSums::new((0..count).map(|i| {
+            let (r, angle) = (1.0 + i as f32, i as f32 * 0.7);
+            (pt2(r * angle.cos(), r * angle.sin()), 1.0 + (i % 3) as f32)
+        }));
+        let target = pt2(0.5, -0.5);
+        group.bench_with_input(BenchmarkId::new("pairwise", count), &count, |b, _| {
+            b.iter(|| sums.pairwise(black_box(target)))
+        });
+        group.bench_with_input(BenchmarkId::new("kernel", count), &count, |b, _| {
+            b.iter(|| sums.kernel(black_box(target)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_step, bench_integration, bench_leaf_sum);
+criterion_main!(benches);
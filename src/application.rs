@@ -1,3 +1,5 @@
+use std::collections::{BTreeMap, HashSet};
+
 use async_std::task;
 use nannou::prelude::*;
 use nannou::wgpu::{Backends, DeviceDescriptor, Limits};
@@ -6,19 +8,62 @@ use MouseScrollDelta::*;
 use nannou_egui::{Egui, egui};
 
 use crate::drawing::{alpha, draw_rect, Drawable};
-use crate::physics::Universe;
+use crate::edit_history::EditHistory;
+use crate::physics::{
+    BoundaryMode, Brush, BrushShape, CollisionMode, Cosmology, DragMode, External, ForceLaw2D, ForceStrategy, Mond, OpeningCriterion, ParticleId, Potential, Region, Solver, UnitSystem, Units, Universe, Yukawa,
+};
 use crate::simulation::Simulation;
+use crate::trails::TrailFilter;
 use crate::view_state::ViewState;
 
 struct AppModel {
     simulation: Simulation<Universe>,
     view_state: ViewState,
     egui: Egui,
+    /// The particle a bond is being dragged from.
+    bond_start: Option<ParticleId>,
+    selected: Option<ParticleId>,
+    /// The velocity being dialled in for the selected particle, applied once the edit finishes.
+    edited_velocity: Option<Vec2>,
+    /// Whether to draw the path the selected particle is predicted to follow.
+    show_prediction: bool,
+    /// The particle the view follows.
+    tracked: Option<ParticleId>,
+    /// The corner a selection box is being dragged from, in universe coordinates.
+    selection_start: Option<Point2>,
+    /// The particles picked by the selection box, which the selection tools edit.
+    selection: Vec<ParticleId>,
+    /// The factor by which the selection tools scale masses and velocities.
+    selection_factor: f32,
+    /// The velocity the selection tools add to the selection.
+    selection_velocity: Vec2,
+    /// The edits made to the universe, for undo and redo.
+    history: EditHistory,
+    /// Where a particle is being launched from, in universe coordinates.
+    launch_start: Option<Point2>,
+    /// The mass and radius of launched particles, before modifiers.
+    launch_mass: f32,
+    launch_radius: f32,
+    brush: Brush,
+    /// Whether left clicks paint with the brush, rather than adding single particles.
+    painting: bool,
 }
 
 const INITIAL_PARTICLE_COUNT: usize = 1000;
 const KEYBOARD_PAN_DISTANCE: f32 = 50.0;
 const ZOOM_FACTOR: f32 = 1.1;
+const INSPECTOR_NEIGHBOURS: usize = 5;
+/// The most names of inspected particles listed.
+const INSPECTOR_NAMES: usize = 10;
+const COSMOLOGY_INITIAL_REDSHIFT: f32 = 3.0;
+/// How close, in pixels, the mouse must be to a particle to pick it.
+const PICK_DISTANCE: f32 = 5.0;
+/// The launch speed per unit length of the drag.
+const LAUNCH_SPEED_PER_LENGTH: f32 = 1.0;
+/// The factor by which holding shift or alt raises or lowers the mass of launched particles.
+const LAUNCH_MASS_MODIFIER: f32 = 10.0;
+const TRAJECTORY_STEPS: usize = 300;
+const TRAJECTORY_DT: f32 = 0.01;
 
 pub fn run_sync() {
     block_on(run_async());
@@ -43,6 +88,21 @@ async fn init_nannou_app(app: &App) -> AppModel {
         egui,
         simulation: Simulation::new(Universe::new(INITIAL_PARTICLE_COUNT)),
         view_state: Default::default(),
+        bond_start: None,
+        selected: None,
+        edited_velocity: None,
+        show_prediction: true,
+        tracked: None,
+        selection_start: None,
+        selection: Vec::new(),
+        selection_factor: 2.0,
+        selection_velocity: Vec2::ZERO,
+        history: EditHistory::default(),
+        launch_start: None,
+        launch_mass: 1000.0,
+        launch_radius: 5.0,
+        brush: Brush::default(),
+        painting: false,
     }
 }
 
@@ -69,17 +129,395 @@ async fn create_window(app: &App) -> WindowId {
 fn update(_app: &App, model: &mut AppModel, _: Update) {
     let egui = &mut model.egui;
     let ctx = egui.begin_frame();
-        let view_state = model.view_state;
+        let inspector = model.view_state.inspector_bounds();
 
     egui::Window::new("Settings").show(&ctx, |ui| {
         // view state
         ui.label("view state:");
-        ui.add(egui::RadioButton::new(view_state.draw_particles,"particles"));
+        ui.add(egui::RadioButton::new(model.view_state.draw_particles,"particles"));
+        let trails = &mut model.view_state.trails;
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut trails.enabled, "trails");
+            ui.add(egui::Slider::new(&mut trails.length, 2..=500).text("length"));
+        });
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut trails.filter, TrailFilter::All, "all");
+            ui.radio_value(&mut trails.filter, TrailFilter::Selected, "selected");
+            ui.radio_value(&mut trails.filter, TrailFilter::Grouped, "grouped");
+        });
         //theta slider
         ui.label("Theta:");
         ui.add(egui::Slider::new(&mut model.simulation.model.theta, 0.0..=1.0));
+        //tree rebuild interval slider
+        ui.label("Tree rebuild interval:");
+        ui.add(egui::Slider::new(&mut model.simulation.model.rebuild_interval, 1..=100));
+        //solver, force strategy & leaf bucket size
+        let universe = &mut model.simulation.model;
+        ui.label("Solver:");
+        ui.radio_value(&mut universe.solver, Solver::BarnesHut, "Barnes-Hut");
+        ui.radio_value(&mut universe.solver, Solver::FastMultipole, "fast multipole");
+        //force law
+        ui.label("Force law:");
+        if ui.radio(universe.force_law == ForceLaw2D::Newtonian, "Newtonian").clicked() {
+            universe.force_law = ForceLaw2D::Newtonian;
+        }
+        if ui.radio(universe.force_law == ForceLaw2D::Coulomb, "Coulomb").clicked() {
+            universe.force_law = ForceLaw2D::Coulomb;
+        }
+        if ui.radio(matches!(universe.force_law, ForceLaw2D::Yukawa(_)), "Yukawa").clicked() {
+            universe.force_law = ForceLaw2D::Yukawa(Yukawa::default());
+        }
+        if ui.radio(matches!(universe.force_law, ForceLaw2D::Mond(_)), "MOND").clicked() {
+            universe.force_law = ForceLaw2D::Mond(Mond::default());
+        }
+        match &mut universe.force_law {
+            ForceLaw2D::Yukawa(yukawa) => {
+                ui.add(egui::Slider::new(&mut yukawa.range, 10.0..=2000.0).logarithmic(true).text("range"));
+            }
+            ForceLaw2D::Mond(mond) => {
+                ui.add(egui::Slider::new(&mut mond.acceleration_scale, 0.01..=100.0).logarithmic(true).text("acceleration scale"));
+            }
+            _ => {}
+        }
+        ui.label("Force evaluation:");
+        ui.radio_value(&mut universe.force_strategy, ForceStrategy::PerBody, "per body");
+        ui.radio_value(&mut universe.force_strategy, ForceStrategy::GroupWalk, "group walk");
+        ui.label("Leaf capacity:");
+        ui.add(egui::Slider::new(&mut universe.leaf_capacity, 1..=32));
+        //cell opening criterion
+        ui.label("Opening criterion:");
+        ui.radio_value(&mut universe.opening_criterion, OpeningCriterion::Geometric, "geometric");
+        ui.radio_value(&mut universe.opening_criterion, OpeningCriterion::BMax, "b max");
+        ui.radio_value(&mut universe.opening_criterion, OpeningCriterion::Relative, "relative");
+        ui.add(egui::Slider::new(&mut universe.relative_tolerance, 0.0001..=0.1).logarithmic(true).text("relative tolerance"));
+        ui.checkbox(&mut universe.guard_inside_cell, "open cells containing the target");
+        //periodic box
+        let mut periodic = universe.periodic_box.is_some();
+        if ui.checkbox(&mut periodic, "periodic box").changed() {
+            universe.periodic_box = if periodic { Some(1000.0) } else { None };
+        }
+        if let Some(box_size) = &mut universe.periodic_box {
+            ui.add(egui::Slider::new(box_size, 100.0..=10000.0).logarithmic(true).text("box size"));
+        }
+        //cosmology
+        let mut expanding = universe.cosmology.is_some();
+        if ui.checkbox(&mut expanding, "cosmological expansion").changed() {
+            universe.cosmology = if expanding { Some(Cosmology::starting_at(COSMOLOGY_INITIAL_REDSHIFT)) } else { None };
+        }
+        if let Some(cosmology) = &mut universe.cosmology {
+            ui.add(egui::Slider::new(&mut cosmology.omega_matter, 0.0..=2.0).text("matter density"));
+            ui.add(egui::Slider::new(&mut cosmology.omega_lambda, 0.0..=2.0).text("dark energy density"));
+            ui.add(egui::Slider::new(&mut cosmology.hubble_constant, 0.001..=1.0).logarithmic(true).text("Hubble constant"));
+            ui.label(format!("z = {:.3}, a = {:.3}, H = {:.4}", cosmology.redshift(), cosmology.scale_factor, cosmology.hubble_parameter()));
+            ui.label("gas pressure, bonds, drag and dynamical friction are off while expanding");
+        }
+        //boundary
+        ui.label("Boundary:");
+        ui.radio_value(&mut universe.boundary.mode, BoundaryMode::None, "none");
+        ui.radio_value(&mut universe.boundary.mode, BoundaryMode::Reflecting, "reflecting");
+        ui.radio_value(&mut universe.boundary.mode, BoundaryMode::Absorbing, "absorbing");
+        ui.radio_value(&mut universe.boundary.mode, BoundaryMode::Wrap, "wrap around");
+        ui.add(egui::Slider::new(&mut universe.boundary.size, 100.0..=10000.0).logarithmic(true).text("boundary size"));
+        //collisions
+        ui.label("Collisions:");
+        ui.radio_value(&mut universe.collision_mode, CollisionMode::None, "none");
+        ui.radio_value(&mut universe.collision_mode, CollisionMode::Merge, "merge");
+        ui.radio_value(&mut universe.collision_mode, CollisionMode::Bounce, "bounce");
+        ui.add(egui::Slider::new(&mut universe.restitution, 0.0..=1.0).text("restitution"));
+        //dissipation
+        ui.label("Drag:");
+        ui.radio_value(&mut universe.drag.mode, DragMode::None, "none");
+        ui.radio_value(&mut universe.drag.mode, DragMode::Linear, "linear");
+        ui.radio_value(&mut universe.drag.mode, DragMode::Quadratic, "quadratic");
+        ui.add(egui::Slider::new(&mut universe.drag.coefficient, 0.0001..=1.0).logarithmic(true).text("drag coefficient"));
+        ui.checkbox(&mut universe.dynamical_friction.enabled, "dynamical friction");
+        if universe.dynamical_friction.enabled {
+            ui.add(egui::Slider::new(&mut universe.dynamical_friction.min_mass, 1.0..=1e5).logarithmic(true).text("minimum mass"));
+            ui.add(egui::Slider::new(&mut universe.dynamical_friction.neighbours, 4..=128).text("neighbours"));
+            ui.add(egui::Slider::new(&mut universe.dynamical_friction.coulomb_logarithm, 0.0..=10.0).text("Coulomb logarithm"));
+        }
+        //post-Newtonian corrections
+        ui.checkbox(&mut universe.post_newtonian.enabled, "post-Newtonian black holes");
+        if universe.post_newtonian.enabled {
+            ui.checkbox(&mut universe.post_newtonian.radiation_reaction, "radiation reaction");
+            ui.horizontal(|ui| {
+                ui.add(egui::Slider::new(&mut universe.post_newtonian.speed_of_light, 10.0..=1e6).logarithmic(true).text("speed of light"));
+                if let Some(speed_of_light) = universe.units.speed_of_light() {
+                    if ui.button("physical").clicked() {
+                        universe.post_newtonian.speed_of_light = speed_of_light;
+                    }
+                }
+            });
+        }
+        //bonds
+        ui.label("New bonds:");
+        ui.add(egui::Slider::new(&mut universe.spring.stiffness, 0.1..=1e4).logarithmic(true).text("stiffness"));
+        ui.add(egui::Slider::new(&mut universe.spring.damping, 0.0..=100.0).text("damping"));
+        ui.add(egui::Slider::new(&mut universe.spring.breaking_strain, 0.0..=5.0).text("breaking strain (0: unbreakable)"));
+        //external potentials
+        ui.label("External potentials:");
+        let mut removed = None;
+        for (i, external) in universe.externals.iter_mut().enumerate() {
+            ui.collapsing(format!("{}: {}", i, external.potential.name()), |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("position:");
+                    ui.add(egui::DragValue::new(&mut external.position.x));
+                    ui.add(egui::DragValue::new(&mut external.position.y));
+                });
+                match &mut external.potential {
+                    Potential::PointMass { mass } => {
+                        ui.add(egui::Slider::new(mass, 1.0..=1e6).logarithmic(true).text("mass"));
+                    }
+                    Potential::Nfw { mass, scale_radius } => {
+                        ui.add(egui::Slider::new(mass, 1.0..=1e6).logarithmic(true).text("mass"));
+                        ui.add(egui::Slider::new(scale_radius, 1.0..=2000.0).logarithmic(true).text("scale radius"));
+                    }
+                    Potential::Logarithmic { circular_velocity, core_radius } => {
+                        ui.add(egui::Slider::new(circular_velocity, 0.0..=500.0).text("circular velocity"));
+                        ui.add(egui::Slider::new(core_radius, 1.0..=2000.0).logarithmic(true).text("core radius"));
+                    }
+                    Potential::MiyamotoNagai { mass, scale_length, scale_height } => {
+                        ui.add(egui::Slider::new(mass, 1.0..=1e6).logarithmic(true).text("mass"));
+                        ui.add(egui::Slider::new(scale_length, 1.0..=2000.0).logarithmic(true).text("scale length"));
+                        ui.add(egui::Slider::new(scale_height, 0.0..=500.0).text("scale height"));
+                    }
+                    Potential::Uniform { field } => {
+                        ui.horizontal(|ui| {
+                            ui.label("field:");
+                            ui.add(egui::DragValue::new(&mut field.x));
+                            ui.add(egui::DragValue::new(&mut field.y));
+                        });
+                    }
+                }
+                if ui.button("remove").clicked() {
+                    removed = Some(i);
+                }
+            });
+        }
+        if let Some(i) = removed {
+            universe.externals.remove(i);
+        }
+        ui.horizontal(|ui| {
+            ui.label("add:");
+            for potential in Potential::defaults() {
+                if ui.button(potential.name()).clicked() {
+                    universe.externals.push(External::new(Point2::ZERO, potential));
+                }
+            }
+        });
+        //units
+        ui.label("Units:");
+        for system in UnitSystem::all() {
+            if ui.radio(universe.units.system == system, system.name()).clicked() {
+                universe.units = Units::new(system);
+            }
+        }
+        if universe.units.system != UnitSystem::Simulation {
+            ui.horizontal(|ui| {
+                ui.label("simulation unit of length, mass, time:");
+                for scale in [&mut universe.units.length, &mut universe.units.mass, &mut universe.units.time] {
+                    let speed = *scale * 0.01;
+                    ui.add(egui::DragValue::new(scale).speed(speed));
+                }
+            });
+        }
+        ui.label(format!("G = {:.3} (simulation units), simulated time {}", universe.grav_const(), universe.units.time(universe.age())));
+        //gas
+        ui.label("Gas:");
+        ui.add(egui::Slider::new(&mut universe.sph.gamma, 1.0..=2.0).text("adiabatic index"));
+        ui.add(egui::Slider::new(&mut universe.sph.neighbours, 4..=64).text("neighbours"));
+        ui.add(egui::Slider::new(&mut universe.sph.viscosity_alpha, 0.0..=2.0).text("viscosity"));
+        //selected particle
+        if let Some(id) = model.selected.filter(|&id| universe.index_of(id).is_some()) {
+            let index = universe.index_of(id).unwrap();
+            ui.label(format!("Selected particle {} of mass {}", id, universe.units.mass(universe.mass_of(index))));
+            if let Some(metadata) = universe.metadata_mut(id) {
+                ui.horizontal(|ui| {
+                    ui.label("name:");
+                    ui.text_edit_singleline(&mut metadata.name);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("group:");
+                    ui.text_edit_singleline(&mut metadata.group);
+                });
+                ui.horizontal(|ui| {
+                    let mut coloured = metadata.colour.is_some();
+                    let mut colour = metadata.colour.map_or([255, 255, 255], |c| [c.red, c.green, c.blue]);
+                    ui.checkbox(&mut coloured, "colour");
+                    ui.color_edit_button_srgb(&mut colour);
+                    metadata.colour = coloured.then(|| rgb(colour[0], colour[1], colour[2]));
+                });
+            }
+            ui.horizontal(|ui| {
+                let mut tracking = model.tracked == Some(id);
+                if ui.checkbox(&mut tracking, "track").changed() {
+                    model.tracked = tracking.then_some(id);
+                }
+                ui.checkbox(&mut model.show_prediction, "show predicted path");
+                if ui.button("remove").clicked() {
+                    model.history.apply(universe, &[id], |universe| {
+                        universe.remove(id);
+                    });
+                }
+            });
+            ui.horizontal(|ui| {
+                let velocity = universe.velocity_of(index);
+                let mut edited = model.edited_velocity.unwrap_or(velocity);
+                let x = ui.add(egui::DragValue::new(&mut edited.x).prefix("vx: "));
+                let y = ui.add(egui::DragValue::new(&mut edited.y).prefix("vy: "));
+                if x.changed() || y.changed() {
+                    model.edited_velocity = Some(edited);
+                }
+                // The new velocity is previewed while it's dragged or typed, and applied as one edit.
+                let finished = x.drag_released() || x.lost_focus() || y.drag_released() || y.lost_focus();
+                if let Some(edited) = model.edited_velocity.filter(|_| finished) {
+                    model.edited_velocity = None;
+                    model.history.apply(universe, &[id], |universe| universe.add_velocity(&[id], edited - velocity));
+                }
+            });
+        }
+        //launch
+        ui.label("Launch (right-drag, shift heavier, alt lighter):");
+        ui.add(egui::Slider::new(&mut model.launch_mass, 1.0..=1e5).logarithmic(true).text("mass"));
+        ui.add(egui::Slider::new(&mut model.launch_radius, 0.5..=50.0).logarithmic(true).text("radius"));
+        //brush
+        ui.checkbox(&mut model.painting, "paint with left click");
+        if model.painting {
+            let brush = &mut model.brush;
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut brush.shape, BrushShape::Cluster, "cluster");
+                ui.radio_value(&mut brush.shape, BrushShape::Disk, "rotating disk");
+                ui.radio_value(&mut brush.shape, BrushShape::Square, "square");
+                ui.radio_value(&mut brush.shape, BrushShape::Stream, "stream");
+            });
+            ui.add(egui::Slider::new(&mut brush.count, 1..=2000).logarithmic(true).text("count"));
+            ui.add(egui::Slider::new(&mut brush.size, 1.0..=1000.0).logarithmic(true).text("size"));
+            ui.add(egui::Slider::new(&mut brush.min_mass, 0.01..=1e4).logarithmic(true).text("min mass"));
+            ui.add(egui::Slider::new(&mut brush.max_mass, 0.01..=1e4).logarithmic(true).text("max mass"));
+            ui.add(egui::Slider::new(&mut brush.mass_slope, 0.0..=4.0).text("mass spectrum slope"));
+            ui.horizontal(|ui| {
+                ui.label("bulk velocity:");
+                ui.add(egui::DragValue::new(&mut brush.velocity.x).prefix("vx: "));
+                ui.add(egui::DragValue::new(&mut brush.velocity.y).prefix("vy: "));
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut brush.orbit_nearest, "orbit nearest body of mass at least");
+                ui.add(egui::DragValue::new(&mut brush.orbit_min_mass).speed(1.0));
+            });
+        }
+        //selection
+        model.selection.retain(|&id| universe.index_of(id).is_some());
+        if !model.selection.is_empty() {
+            let selection = &model.selection.clone();
+            ui.label(format!("{} particles selected", selection.len()));
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut model.selection_factor).speed(0.01).prefix("factor: "));
+                let factor = model.selection_factor;
+                if ui.button("scale masses").clicked() {
+                    model.history.apply(universe, selection, |universe| universe.scale_masses(selection, factor));
+                }
+                if ui.button("scale velocities").clicked() {
+                    model.history.apply(universe, selection, |universe| universe.scale_velocities(selection, factor));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut model.selection_velocity.x).prefix("vx: "));
+                ui.add(egui::DragValue::new(&mut model.selection_velocity.y).prefix("vy: "));
+                let velocity = model.selection_velocity;
+                if ui.button("add velocity").clicked() {
+                    model.history.apply(universe, selection, |universe| universe.add_velocity(selection, velocity));
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui.button("delete").clicked() {
+                    model.history.apply(universe, selection, |universe| {
+                        universe.remove_all(selection);
+                    });
+                }
+                if ui.button("deselect").clicked() {
+                    model.selection.clear();
+                }
+            });
+        } else {
+            ui.label("Shift-drag to select particles");
+        }
+        ui.horizontal(|ui| {
+            if ui.add_enabled(model.history.can_undo(), egui::Button::new("undo (ctrl+z)")).clicked() {
+                model.history.undo(universe);
+            }
+            if ui.add_enabled(model.history.can_redo(), egui::Button::new("redo (ctrl+y)")).clicked() {
+                model.history.redo(universe);
+            }
+        });
+        //inspector
+        if let Some(bounds) = inspector {
+            let inspected = universe.particles_in_rect(bounds);
+            let mass: f32 = inspected.iter().map(|&index| universe.mass_of(index)).sum();
+            let units = universe.units;
+            ui.label(format!("Inspecting {} particles of total mass {}", inspected.len(), units.mass(mass)));
+            if mass > 0.0 {
+                let (centre, momentum) = inspected.iter().fold((Vec2::ZERO, Vec2::ZERO), |(centre, momentum), &index| {
+                    let mass = universe.mass_of(index);
+                    (centre + universe.position_of(index) * mass, momentum + universe.velocity_of(index) * mass)
+                });
+                let (centre, velocity) = (centre / mass, momentum / mass);
+                ui.label(format!("centre of mass: ({}, {})", units.length(centre.x), units.length(centre.y)));
+                ui.label(format!("mean velocity: ({}, {})", units.velocity(velocity.x), units.velocity(velocity.y)));
+            }
+            // The groups of the inspected particles, and the named ones in their own colours.
+            let mut groups: BTreeMap<&str, usize> = BTreeMap::new();
+            let mut named = Vec::new();
+            for metadata in inspected.iter().filter_map(|&index| universe.metadata(universe.id_of(index))) {
+                if !metadata.group.is_empty() {
+                    *groups.entry(metadata.group.as_str()).or_default() += 1;
+                }
+                if !metadata.name.is_empty() {
+                    named.push(metadata);
+                }
+            }
+            for (group, count) in groups {
+                ui.label(format!("group {}: {} particles", group, count));
+            }
+            for metadata in named.iter().take(INSPECTOR_NAMES) {
+                match metadata.colour {
+                    Some(colour) => ui.colored_label(egui::Color32::from_rgb(colour.red, colour.green, colour.blue), &metadata.name),
+                    None => ui.label(&metadata.name),
+                };
+            }
+            if named.len() > INSPECTOR_NAMES {
+                ui.label(format!("and {} more named", named.len() - INSPECTOR_NAMES));
+            }
+        }
     });
     model.simulation.update();
+    record_trails(model);
+
+    if let Some(id) = model.tracked {
+        let universe = &model.simulation.model;
+        match universe.index_of(id) {
+            Some(index) => model.view_state.pan = -universe.position_of(index) * model.view_state.scale,
+            None => model.tracked = None,
+        }
+    }
+}
+/// Adds the positions of the particles which leave trails to their trails.
+fn record_trails(model: &mut AppModel) {
+    let universe = &model.simulation.model;
+    let trails = &mut model.view_state.trails;
+    let filter = trails.filter;
+    let selection: HashSet<ParticleId> = match filter {
+        TrailFilter::Selected => model.selection.iter().copied().chain(model.selected).collect(),
+        _ => HashSet::new(),
+    };
+    let selected = |id| selection.contains(&id);
+    let grouped = |id| matches!(universe.metadata(id), Some(metadata) if !metadata.group.is_empty());
+    trails.record(universe.ids().iter().enumerate()
+        .filter(|&(_, &id)| match filter {
+            TrailFilter::All => true,
+            TrailFilter::Selected => selected(id),
+            TrailFilter::Grouped => grouped(id),
+        })
+        .map(|(index, &id)| (id, universe.position_of(index))));
 }
 fn raw_window_event(_app: &App, model: &mut AppModel, event: &nannou::winit::event::WindowEvent) {
     // Let egui handle things like keyboard and mouse input.
@@ -97,6 +535,71 @@ fn view(app: &App, app_model: &AppModel, frame: Frame) {
     if let Some(inspector) = app_model.view_state.inspector_app_bounds() {
         draw_rect(inspector, &app_draw, alpha(LIGHTCORAL, 0.8));
     }
+    if let Some(inspector) = app_model.view_state.inspector_bounds() {
+        for index in universe.nearest_particles(inspector.xy(), INSPECTOR_NEIGHBOURS) {
+            sim_draw.line()
+                .start(inspector.xy())
+                .end(universe.position_of(index))
+                .stroke_weight(1.0 / app_model.view_state.scale)
+                .color(alpha(LIGHTCORAL, 0.5));
+        }
+    }
+    let scale = app_model.view_state.scale;
+    for index in app_model.selection.iter().filter_map(|&id| universe.index_of(id)) {
+        sim_draw.ellipse()
+            .xy(universe.position_of(index))
+            .radius(4.0 / scale)
+            .stroke_weight(1.0 / scale)
+            .stroke_color(alpha(LIGHTSKYBLUE, 0.8))
+            .no_fill();
+    }
+    if let Some(start) = app_model.selection_start {
+        let end = app_model.view_state.as_universe_point(app.mouse.position());
+        let rect = Rect::from_corners(start, end);
+        sim_draw.rect()
+            .xy(rect.xy())
+            .wh(rect.wh())
+            .stroke_weight(1.0 / scale)
+            .stroke_color(alpha(LIGHTSKYBLUE, 0.8))
+            .no_fill();
+    }
+    if let Some(index) = app_model.selected.filter(|_| app_model.show_prediction).and_then(|id| universe.index_of(id)) {
+        let velocity = app_model.edited_velocity.unwrap_or_else(|| universe.velocity_of(index));
+        let trajectory = universe.predict_trajectory_of(index, velocity, TRAJECTORY_STEPS, TRAJECTORY_DT);
+        sim_draw.polyline()
+            .stroke_weight(1.0 / scale)
+            .points(trajectory)
+            .color(alpha(GOLD, 0.5));
+    }
+    if let Some(start) = app_model.launch_start {
+        let end = app_model.view_state.as_universe_point(app.mouse.position());
+        let (mass, radius) = launch_mass_and_radius(app, app_model.launch_mass, app_model.launch_radius);
+        let velocity = (end - start) * LAUNCH_SPEED_PER_LENGTH;
+        let trajectory = universe.predict_trajectory(start, velocity, mass, TRAJECTORY_STEPS, TRAJECTORY_DT);
+        sim_draw.polyline()
+            .stroke_weight(1.0 / scale)
+            .points(trajectory)
+            .color(alpha(GOLD, 0.5));
+        sim_draw.ellipse()
+            .xy(start)
+            .radius(radius)
+            .stroke_weight(1.0 / scale)
+            .stroke_color(alpha(GOLD, 0.8))
+            .no_fill();
+        sim_draw.arrow()
+            .start(start)
+            .end(end)
+            .stroke_weight(1.0 / scale)
+            .color(alpha(GOLD, 0.8));
+    }
+    // The particle may have gone since the drag started.
+    if let Some(start) = app_model.bond_start.and_then(|id| universe.index_of(id)) {
+        sim_draw.line()
+            .start(universe.position_of(start))
+            .end(app_model.view_state.as_universe_point(app.mouse.position()))
+            .stroke_weight(1.0 / app_model.view_state.scale)
+            .color(alpha(LIGHTGREEN, 0.8));
+    }
     // Write the result of our drawing to the window's frame.
     app_draw.to_frame(app, &frame).unwrap();
     //ui stuff
@@ -104,6 +607,18 @@ fn view(app: &App, app_model: &AppModel, frame: Frame) {
 }
 
 fn event_handler(app: &App, model: &mut AppModel, event: WindowEvent) {
+    // Leave input meant for the settings window to egui, but let releases through so that drags
+    // begun over the universe always end.
+    let ctx = model.egui.ctx();
+    let for_egui = match event {
+        KeyPressed(_) | KeyReleased(_) | ReceivedCharacter(_) => ctx.wants_keyboard_input(),
+        MousePressed(_) | MouseMoved(_) | MouseWheel(..) => ctx.wants_pointer_input(),
+        _ => false,
+    };
+    if for_egui {
+        return;
+    }
+
     let view = &mut model.view_state;
     let universe = &mut model.simulation.model;
     let mouse = &app.mouse;
@@ -117,13 +632,53 @@ fn event_handler(app: &App, model: &mut AppModel, event: WindowEvent) {
             view.inspect_at(position);
         }
         MouseReleased(MouseButton::Middle) => view.end_mouse_pan(),
+        MousePressed(MouseButton::Left) if app.keys.mods.shift() => {
+            model.selection_start = Some(view.as_universe_point(app.mouse.position()));
+        }
+        MousePressed(MouseButton::Left) if model.painting => {
+            let universe_position = view.as_universe_point(app.mouse.position());
+            let brush = model.brush;
+            model.history.apply(universe, &[], |universe| universe.paint(&brush, universe_position));
+        }
         MousePressed(MouseButton::Left) => {
             let universe_position = view.as_universe_point(app.mouse.position());
-            universe.add_particle_at(universe_position);
+            // Dragging from a particle bonds it to another, clicking elsewhere adds one.
+            let picked = universe.particle_at(universe_position, PICK_DISTANCE / view.scale);
+            model.bond_start = picked.map(|index| universe.id_of(index));
+            if model.bond_start.is_none() {
+                model.history.apply(universe, &[], |universe| universe.add_particle_at(universe_position));
+            }
+        }
+        MouseReleased(MouseButton::Left) => {
+            if let Some(start) = model.selection_start.take() {
+                let end = view.as_universe_point(app.mouse.position());
+                model.selection = universe.ids_in(Region::Rect(Rect::from_corners(start, end)));
+            }
+            // Releasing over the same particle selects it instead.
+            if let Some(start) = model.bond_start.take().and_then(|id| universe.index_of(id)) {
+                let universe_position = view.as_universe_point(app.mouse.position());
+                match universe.particle_at(universe_position, PICK_DISTANCE / view.scale) {
+                    Some(end) if end == start => {
+                        model.selected = Some(universe.id_of(start));
+                        model.edited_velocity = None;
+                    }
+                    Some(end) => {
+                        let ends = [universe.id_of(start), universe.id_of(end)];
+                        model.history.apply(universe, &ends, |universe| universe.add_bond(start, end));
+                    }
+                    None => {}
+                }
+            }
         }
         MousePressed(MouseButton::Right) => {
-            let universe_position = view.as_universe_point(app.mouse.position());
-            universe.add_moving_particle_at(universe_position);
+            model.launch_start = Some(view.as_universe_point(app.mouse.position()));
+        }
+        MouseReleased(MouseButton::Right) => {
+            if let Some(start) = model.launch_start.take() {
+                let velocity = (view.as_universe_point(app.mouse.position()) - start) * LAUNCH_SPEED_PER_LENGTH;
+                let (mass, radius) = launch_mass_and_radius(app, model.launch_mass, model.launch_radius);
+                model.history.apply(universe, &[], |universe| universe.launch_particle_at(start, velocity, mass, radius));
+            }
         }
 
         MouseWheel(LineDelta(x, y), _phase) => {
@@ -132,22 +687,66 @@ fn event_handler(app: &App, model: &mut AppModel, event: WindowEvent) {
 
         // key events:
         KeyPressed(Key::Space) => view.cycle_drawn_stuff(),
-        KeyPressed(Key::Back /* backspace */) => universe.clear(),
-        KeyPressed(Key::P) => universe.add_random_particles(200),
-        KeyPressed(Key::U) => universe.add_uniform_random(200),
+        KeyPressed(Key::Back /* backspace */) => {
+            let ids = universe.ids().to_vec();
+            model.history.apply(universe, &ids, Universe::clear);
+        }
+        KeyPressed(Key::P) => model.history.apply(universe, &[], |universe| universe.add_random_particles(200)),
+        KeyPressed(Key::U) => model.history.apply(universe, &[], |universe| universe.add_uniform_random(200)),
+        KeyPressed(Key::G) => model.history.apply(universe, &[], |universe| universe.add_random_gas(200)),
+        KeyPressed(Key::C) => model.history.apply(universe, &[], |universe| universe.add_random_plasma(200)),
+        KeyPressed(Key::B) => {
+            let universe_position = view.as_universe_point(app.mouse.position());
+            model.history.apply(universe, &[], |universe| universe.add_binary_black_hole(universe_position, 100.0, 1e3));
+        }
         KeyPressed(Key::R) => {
             view.reset_zoom();
             view.reset_pan();
         }
         KeyPressed(Key::S) => model.simulation.reset_stats(),
+        KeyPressed(Key::Z) if app.keys.mods.ctrl() => {
+            model.history.undo(universe);
+        }
+        KeyPressed(Key::Y) if app.keys.mods.ctrl() => {
+            model.history.redo(universe);
+        }
+        KeyPressed(Key::Delete) if !model.selection.is_empty() => {
+            let selection = &model.selection;
+            model.history.apply(universe, selection, |universe| {
+                universe.remove_all(selection);
+            });
+        }
         KeyPressed(Key::Up) => view.pan.y -= KEYBOARD_PAN_DISTANCE,
         KeyPressed(Key::Down) => view.pan.y += KEYBOARD_PAN_DISTANCE,
         KeyPressed(Key::Left) => view.pan.x += KEYBOARD_PAN_DISTANCE,
         KeyPressed(Key::Right) => view.pan.x += -KEYBOARD_PAN_DISTANCE,
-        KeyPressed(Key::Equals) => universe.multiply_black_hole_mass(2.0),
-        KeyPressed(Key::Minus) => universe.multiply_black_hole_mass(0.5),
-        KeyPressed(Key::Key0) => universe.multiply_black_hole_mass(0.0),
-        KeyPressed(Key::Key9) => universe.set_black_hole_mass(1e3),
+        KeyPressed(Key::Equals) => {
+            let black_holes = universe.black_holes();
+            model.history.apply(universe, &black_holes, |universe| universe.multiply_black_hole_mass(2.0));
+        }
+        KeyPressed(Key::Minus) => {
+            let black_holes = universe.black_holes();
+            model.history.apply(universe, &black_holes, |universe| universe.multiply_black_hole_mass(0.5));
+        }
+        KeyPressed(Key::Key0) => {
+            let black_holes = universe.black_holes();
+            model.history.apply(universe, &black_holes, |universe| universe.multiply_black_hole_mass(0.0));
+        }
+        KeyPressed(Key::Key9) => {
+            let black_holes = universe.black_holes();
+            model.history.apply(universe, &black_holes, |universe| universe.set_black_hole_mass(1e3));
+        }
         _ => {}
     }
 }
+
+/// The mass and radius of a launched particle from those set: holding shift makes it heavier, and
+/// alt lighter, at the same density.
+fn launch_mass_and_radius(app: &App, mass: f32, radius: f32) -> (f32, f32) {
+    let factor = match (app.keys.mods.shift(), app.keys.mods.alt()) {
+        (true, false) => LAUNCH_MASS_MODIFIER,
+        (false, true) => 1.0 / LAUNCH_MASS_MODIFIER,
+        _ => 1.0,
+    };
+    (mass * factor, radius * factor.cbrt())
+}
@@ -0,0 +1,177 @@
+use crate::physics::{ParticleId, Snapshot, Universe};
+
+/// The number of edits which can be undone.
+const HISTORY_LENGTH: usize = 100;
+
+/// An edit of the universe, recorded as the state of the particles it touched before and after it
+/// so that it can be undone and redone.  Undoing reverses the changes the edit made to those
+/// particles, leaving whatever else has happened to them since.
+struct Edit {
+    before: Snapshot,
+    after: Snapshot,
+    /// The particles the edit added, which undoing removes.
+    added: Vec<ParticleId>,
+    /// The particles the edit removed, which redoing removes again.
+    removed: Vec<ParticleId>,
+}
+
+impl Edit {
+    fn undo(&self, universe: &mut Universe) {
+        universe.remove_all(&self.added);
+        universe.apply_change(&self.after, &self.before);
+    }
+
+    fn redo(&self, universe: &mut Universe) {
+        universe.remove_all(&self.removed);
+        universe.apply_change(&self.before, &self.after);
+    }
+}
+
+/// The edits made to a universe, which can be undone and redone in turn.
+#[derive(Default)]
+pub struct EditHistory {
+    done: Vec<Edit>,
+    undone: Vec<Edit>,
+}
+
+impl EditHistory {
+    /// Applies `edit` to the universe and records it.  `ids` must include every existing particle
+    /// the edit may change or remove; those it adds are found by their new ids.
+    pub fn apply(
+        &mut self,
+        universe: &mut Universe,
+        ids: &[ParticleId],
+        edit: impl FnOnce(&mut Universe),
+    ) {
+        let before = universe.snapshot(ids);
+        // Ids are never reused, so the edit's particles are newer than any existing one.
+        let newest = universe.ids().iter().max().copied();
+        edit(universe);
+
+        let added: Vec<ParticleId> = universe
+            .ids()
+            .iter()
+            .filter(|&&id| Some(id) > newest)
+            .copied()
+            .collect();
+        let touched: Vec<ParticleId> = before.ids().chain(added.iter().copied()).collect();
+        let after = universe.snapshot(&touched);
+        let removed = before
+            .ids()
+            .filter(|&id| universe.index_of(id).is_none())
+            .collect();
+
+        self.undone.clear();
+        self.done.push(Edit {
+            before,
+            after,
+            added,
+            removed,
+        });
+        if self.done.len() > HISTORY_LENGTH {
+            self.done.remove(0);
+        }
+    }
+
+    /// Undoes the last edit, returning whether there was one.
+    pub fn undo(&mut self, universe: &mut Universe) -> bool {
+        match self.done.pop() {
+            Some(edit) => {
+                edit.undo(universe);
+                self.undone.push(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Redoes the last edit undone, returning whether there was one.
+    pub fn redo(&mut self, universe: &mut Universe) -> bool {
+        match self.undone.pop() {
+            Some(edit) => {
+                edit.redo(universe);
+                self.done.push(edit);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.done.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nannou::geom::{pt2, vec2};
+
+    use super::*;
+    use crate::simulation::Model;
+
+    #[test]
+    fn test_undo_and_redo() {
+        let mut universe = Universe::default();
+        let mut history = EditHistory::default();
+        history.apply(&mut universe, &[], |universe| {
+            universe.add_particle_at(pt2(0.0, 0.0));
+            universe.add_particle_at(pt2(50.0, 0.0));
+        });
+        let ids = universe.ids().to_vec();
+        history.apply(&mut universe, &ids, |universe| universe.add_bond(0, 1));
+        history.apply(&mut universe, &ids, |universe| {
+            universe.scale_masses(&ids, 2.0)
+        });
+        history.apply(&mut universe, &ids, Universe::clear);
+        assert!(universe.ids().is_empty());
+
+        assert!(history.undo(&mut universe));
+        assert_eq!(universe.ids(), &ids[..]);
+        assert_eq!(universe.mass_of(1), 2000.0);
+        assert!(history.undo(&mut universe));
+        assert_eq!(universe.mass_of(1), 1000.0);
+
+        assert!(history.redo(&mut universe));
+        assert_eq!(universe.mass_of(1), 2000.0);
+        assert!(history.redo(&mut universe));
+        assert!(universe.ids().is_empty());
+        assert!(!history.can_redo());
+
+        while history.undo(&mut universe) {}
+        assert!(universe.ids().is_empty());
+        assert!(history.redo(&mut universe));
+        assert_eq!(universe.ids(), &ids[..]);
+    }
+
+    #[test]
+    fn test_undo_keeps_motion_since() {
+        let mut universe = Universe::default();
+        for x in [0.0, 50.0, 100.0] {
+            universe.add_particle_at(pt2(x, 0.0));
+        }
+        let ids = universe.ids().to_vec();
+        let mut history = EditHistory::default();
+        history.apply(&mut universe, &ids[..1], |universe| {
+            universe.add_velocity(&ids[..1], vec2(0.0, 10.0))
+        });
+        history.apply(&mut universe, &ids[1..2], |universe| {
+            universe.scale_masses(&ids[1..2], 2.0)
+        });
+        universe.step(1.0);
+        let positions: Vec<_> = (0..3).map(|i| universe.position_of(i)).collect();
+        let velocities: Vec<_> = (0..3).map(|i| universe.velocity_of(i)).collect();
+
+        assert!(history.undo(&mut universe));
+        assert!(history.undo(&mut universe));
+        assert_eq!(universe.mass_of(1), 1000.0);
+        assert_eq!(universe.velocity_of(0), velocities[0] - vec2(0.0, 10.0));
+        let moved: Vec<_> = (0..3).map(|i| universe.position_of(i)).collect();
+        assert_eq!(moved, positions);
+        assert_eq!(universe.velocity_of(1), velocities[1]);
+        assert_eq!(universe.velocity_of(2), velocities[2]);
+    }
+}
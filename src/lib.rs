@@ -15,8 +15,10 @@ mod macros;
 pub mod application;
 mod created;
 mod drawing;
-mod physics;
-mod simulation;
+mod edit_history;
+pub mod physics;
+pub mod simulation;
+mod trails;
 mod view_state;
 #[cfg(target_arch = "wasm32")]
 mod wasm;
@@ -1,13 +1,320 @@
+use std::cmp::Ordering;
 use std::ops::AddAssign;
 
-use nannou::geom::{vec2, Rect};
+use nannou::geom::{vec2, Point2, Rect};
 
 use crate::physics::space_2d::Space2D;
 
+use super::ewald::PeriodicBox;
+use super::force_law::{ForceLaw, ForceLaw2D, Moments, Newtonian};
 use super::point_mass::PointMass;
-use super::space::DivisibleSpace;
+use super::solver::GravitySolver;
+use super::space::{DivisibleSpace, PeriodicSpace, Space};
 
-pub type GravityField2D = GravityField<Space2D, 4>;
+pub type GravityField2D = GravityField<Space2D, 4, ForceLaw2D>;
+
+/// How the `GravitySolver` implementation of `GravityField` walks the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Derivative)]
+#[derivative(Default)]
+pub enum ForceStrategy {
+    /// Walk the tree separately for every body.
+    #[derivative(Default)]
+    PerBody,
+    /// Walk the tree once per leaf bucket, building a single interaction list which is then
+    /// applied to all the bodies in the bucket.
+    GroupWalk,
+}
+
+/// How a tree walk decides whether a cell is far enough from its target for the cell's total mass
+/// to stand in for its contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Derivative)]
+#[derivative(Default)]
+pub enum OpeningCriterion {
+    /// Accept when `width <= theta * d`, `d` being the distance from the target to the cell's
+    /// centre of mass.
+    #[derivative(Default)]
+    Geometric,
+    /// Salmon & Warren's criterion: accept when `b_max <= theta * d`, `b_max` being the distance
+    /// from the centre of mass to the cell's furthest corner, so that cells whose centre of mass
+    /// lies near an edge are treated more carefully.
+    BMax,
+    /// The relative criterion used by GADGET-2: accept when the size of the first neglected term,
+    /// roughly `G * Q * width^2 / d^4` for a cell of total absolute strength `Q`, is within
+    /// `relative_tolerance` of the target's acceleration at the previous step.  Falls back to
+    /// `Geometric` where that isn't known.
+    Relative,
+}
+
+/// The settings of the test for opening cells during a tree walk.
+#[derive(Debug, Clone, Copy)]
+pub struct Opening<S: Space> {
+    pub criterion: OpeningCriterion,
+    /// Always open cells which contain the target, as their centre of mass may otherwise be far
+    /// enough away for them to be accepted.
+    pub guard_inside_cell: bool,
+    pub relative_tolerance: S::Scalar,
+}
+
+impl<S: Space> Default for Opening<S> {
+    fn default() -> Self {
+        Self {
+            criterion: OpeningCriterion::default(),
+            guard_inside_cell: false,
+            relative_tolerance: S::SCALAR_ZERO,
+        }
+    }
+}
+
+/// The parameters of a walk of the tree on behalf of a single target, or group of targets.
+#[derive(Debug, Clone, Copy)]
+struct Walk<'a, S: PeriodicSpace, L: ForceLaw<S>> {
+    law: L,
+    opening: Opening<S>,
+    theta_squared: S::Scalar,
+    grav_const: S::Scalar,
+    /// The magnitude of the target's acceleration at the previous step, or zero if unknown.
+    previous_g: S::Scalar,
+    periodic: Option<&'a PeriodicBox<S>>,
+    /// The index and position in the tree of a body to leave out of the field.
+    excluded: Option<(usize, S::Vector)>,
+}
+
+impl<'a, S: PeriodicSpace, L: ForceLaw<S>> Walk<'a, S, L> {
+    /// The offset from `target` to `position`, or to its nearest image in a periodic box.
+    fn offset(&self, target: S::Vector, position: S::Vector) -> S::Vector {
+        match self.periodic {
+            Some(periodic) => periodic.offset(target, position),
+            None => position - target,
+        }
+    }
+
+    /// The field of `source`, whose mass is its signed strength, at `target`, including that of
+    /// all its images in a periodic box.
+    fn g_from(&self, source: &PointMass<S>, target: S::Vector) -> S::Vector {
+        let offset = self.offset(target, source.position);
+        let g = self.law.field(source.mass, offset, self.grav_const);
+        match (
+            self.periodic,
+            self.law.inverse_square_coupling(self.grav_const),
+        ) {
+            (Some(periodic), Some(coupling)) => {
+                g + periodic.ewald_correction(offset) * (source.mass * coupling)
+            }
+            _ => g,
+        }
+    }
+
+    /// The constant of the force law if the leaf kernel can sum sources' fields directly, which
+    /// needs an inverse-square law without periodic images.
+    fn direct_coupling(&self) -> Option<S::Scalar> {
+        match self.periodic {
+            Some(_) => None,
+            None => self.law.inverse_square_coupling(self.grav_const),
+        }
+    }
+
+    /// The magnitude of the force law's constant, which with a source's strength bounds its
+    /// field at a given distance.  Laws which aren't inverse-square are screened forms of gravity,
+    /// so the gravitational constant bounds them.
+    fn coupling_magnitude(&self) -> S::Scalar {
+        let coupling = self
+            .law
+            .inverse_square_coupling(self.grav_const)
+            .unwrap_or(self.grav_const);
+        if coupling < S::SCALAR_ZERO {
+            S::SCALAR_ZERO - coupling
+        } else {
+            coupling
+        }
+    }
+
+    fn g_from_moments(&self, moments: &Moments<S>, target: S::Vector) -> S::Vector {
+        let mut sum = S::VECTOR_ZERO;
+        for source in moments.sources() {
+            sum += self.g_from(&source, target);
+        }
+        sum
+    }
+}
+
+/// A point mass tagged with the index of the particle it represents, so that it can be tracked
+/// across refits.
+#[derive(Debug, Clone, Copy)]
+pub struct Body<S: Space> {
+    pub index: usize,
+    pub point_mass: PointMass<S>,
+    /// The strength with which the body sources the field under the field's force law.
+    pub strength: S::Scalar,
+}
+
+impl<S: Space> Body<S> {
+    pub fn new(index: usize, point_mass: PointMass<S>) -> Self {
+        Self::with_strength(index, point_mass, point_mass.mass)
+    }
+
+    pub fn with_strength(index: usize, point_mass: PointMass<S>, strength: S::Scalar) -> Self {
+        Self {
+            index,
+            point_mass,
+            strength,
+        }
+    }
+
+    /// The body as a point source, whose mass is its strength.
+    fn source(&self) -> PointMass<S> {
+        PointMass::new(self.point_mass.position, self.strength)
+    }
+
+    fn moments(&self) -> Moments<S> {
+        Moments::of(self.point_mass.position, self.strength)
+    }
+}
+
+/// The point sources making up an interaction list, with their positions and strengths in
+/// separate arrays so that the leaf kernel reads each from contiguous memory.
+#[derive(Debug, Clone, Default)]
+struct Interactions<S: Space> {
+    positions: Vec<S::Vector>,
+    strengths: Vec<S::Scalar>,
+}
+
+impl<S: Space> Interactions<S> {
+    /// Empties the list, keeping its capacity.
+    fn clear(&mut self) {
+        self.positions.clear();
+        self.strengths.clear();
+    }
+
+    fn extend(&mut self, sources: impl IntoIterator<Item = PointMass<S>>) {
+        for source in sources {
+            self.positions.push(source.position);
+            self.strengths.push(source.mass);
+        }
+    }
+
+    fn sources(&self) -> impl Iterator<Item = PointMass<S>> + '_ {
+        self.positions
+            .iter()
+            .zip(&self.strengths)
+            .map(|(&position, &strength)| PointMass::new(position, strength))
+    }
+}
+
+impl<S: PeriodicSpace> Interactions<S> {
+    /// The sources' field at `target`, summed by the leaf kernel where the force law allows.
+    fn g_at<L: ForceLaw<S>>(&self, target: S::Vector, walk: &Walk<S, L>) -> S::Vector {
+        match walk.direct_coupling() {
+            Some(coupling) => {
+                S::inverse_square_sum(target, &self.positions, &self.strengths) * coupling
+            }
+            None => {
+                let mut sum = S::VECTOR_ZERO;
+                for source in self.sources() {
+                    sum += walk.g_from(&source, target);
+                }
+                sum
+            }
+        }
+    }
+}
+
+/// An interaction list whose field can be summed both pair by pair with `g_from`, as the tree walk
+/// used to, and with the leaf kernel, for the benchmarks to compare.
+pub struct LeafSums {
+    field: GravityField2D,
+    interactions: Interactions<Space2D>,
+}
+
+impl LeafSums {
+    pub fn new(sources: impl IntoIterator<Item = (Point2, f32)>) -> Self {
+        let mut interactions = Interactions::default();
+        interactions.extend(
+            sources
+                .into_iter()
+                .map(|(position, strength)| PointMass::new(position, strength)),
+        );
+        Self {
+            field: GravityField2D::new(1.0, 1),
+            interactions,
+        }
+    }
+
+    pub fn pairwise(&self, target: Point2) -> Point2 {
+        let walk = self.field.walk(0.0, 1.0, 0.0);
+        let mut sum = Point2::ZERO;
+        for source in self.interactions.sources() {
+            sum += walk.g_from(&source, target);
+        }
+        sum
+    }
+
+    pub fn kernel(&self, target: Point2) -> Point2 {
+        self.interactions
+            .g_at(target, &self.field.walk(0.0, 1.0, 0.0))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Leaf<S: Space> {
+    total: PointMass<S>,
+    moments: Moments<S>,
+    /// Holds at most the field's `leaf_capacity` bodies, unless they coincide in a cell too small to
+    /// subdivide.
+    bodies: Vec<Body<S>>,
+    /// The bodies as point sources, kept alongside them for the leaf kernel.
+    sources: Interactions<S>,
+}
+
+impl<S: Space> Leaf<S> {
+    fn new(body: Body<S>) -> Self {
+        let mut sources = Interactions::default();
+        sources.extend([body.source()]);
+        Self {
+            total: body.point_mass,
+            moments: body.moments(),
+            bodies: vec![body],
+            sources,
+        }
+    }
+
+    fn push(&mut self, body: Body<S>) {
+        self.total += body.point_mass;
+        self.moments += body.moments();
+        self.sources.extend([body.source()]);
+        self.bodies.push(body);
+    }
+
+    /// The distance from the leaf's centre of mass to its furthest body.
+    fn radius(&self) -> S::Scalar {
+        self.bodies.iter().fold(S::SCALAR_ZERO, |radius, body| {
+            let distance = S::magnitude(body.point_mass.position - self.total.position);
+            if distance > radius {
+                distance
+            } else {
+                radius
+            }
+        })
+    }
+}
+
+impl<S: PeriodicSpace> Leaf<S> {
+    /// Applies the interaction list to each body in the leaf, returning `(index, g)` pairs.
+    fn apply_interactions<L: ForceLaw<S>>(
+        &self,
+        interactions: &Interactions<S>,
+        walk: &Walk<S, L>,
+    ) -> Vec<(usize, S::Vector)> {
+        self.bodies
+            .iter()
+            .map(|body| {
+                (
+                    body.index,
+                    interactions.g_at(body.point_mass.position, walk),
+                )
+            })
+            .collect()
+    }
+}
 
 #[derive(Debug, Clone, Derivative)]
 #[derivative(Default)]
@@ -17,10 +324,31 @@ where
 {
     #[derivative(Default)]
     Empty,
-    Body(PointMass<S>),
+    Leaf(Leaf<S>),
     Aggregate(Box<MassAggregate<S, NUM_SUBDIVISIONS>>),
 }
 
+impl<S, const NUM_SUBDIVISIONS: usize> Child<S, NUM_SUBDIVISIONS>
+where
+    S: DivisibleSpace<NUM_SUBDIVISIONS>,
+{
+    fn total(&self) -> PointMass<S> {
+        match self {
+            Child::Empty => PointMass::default(),
+            Child::Leaf(leaf) => leaf.total,
+            Child::Aggregate(aggregate) => aggregate.total,
+        }
+    }
+
+    fn moments(&self) -> Moments<S> {
+        match self {
+            Child::Empty => Moments::default(),
+            Child::Leaf(leaf) => leaf.moments,
+            Child::Aggregate(aggregate) => aggregate.moments,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct MassAggregate<S, const NUM_SUBDIVISIONS: usize>
 where
@@ -28,6 +356,8 @@ where
 {
     /// The mass and center of mass of all points at or under this node.
     total: PointMass<S>,
+    /// The moments of their strengths under the field's force law.
+    moments: Moments<S>,
     pivot: S::Vector,
     width: S::Scalar,
 
@@ -41,6 +371,7 @@ where
     pub fn new(pivot: S::Vector, width: S::Scalar) -> MassAggregate<S, NUM_SUBDIVISIONS> {
         MassAggregate {
             total: PointMass::default(),
+            moments: Moments::default(),
             pivot,
             width,
             subdivisions: S::subdivisions_array_default(),
@@ -52,6 +383,24 @@ impl MassAggregate<Space2D, 4> {
     pub(crate) fn get_bounding_rect(&self) -> Rect {
         Rect::from_xy_wh(self.pivot, vec2(self.width, self.width))
     }
+
+    fn collect_in_rect(&self, rect: Rect, found: &mut Vec<usize>) {
+        if self.get_bounding_rect().overlap(rect).is_none() {
+            return;
+        }
+        for child in &self.subdivisions {
+            match child {
+                Child::Empty => {}
+                Child::Leaf(leaf) => found.extend(
+                    leaf.bodies
+                        .iter()
+                        .filter(|body| rect.contains(body.point_mass.position))
+                        .map(|body| body.index),
+                ),
+                Child::Aggregate(aggregate) => aggregate.collect_in_rect(rect, found),
+            }
+        }
+    }
 }
 
 // impl<S, const NUM_SUBDIVISIONS: usize> Default for MassAggregate<S, NUM_SUBDIVISIONS>
@@ -70,97 +419,345 @@ impl<S, const NUM_SUBDIVISIONS: usize> MassAggregate<S, NUM_SUBDIVISIONS>
 where
     S: DivisibleSpace<NUM_SUBDIVISIONS>,
 {
-    fn insert(&mut self, body: PointMass<S>) {
-        self.total += body;
-        let subdivision_index = S::subdivision_index(self.pivot, body.position);
+    fn insert(&mut self, body: Body<S>, leaf_capacity: usize) {
+        self.total += body.point_mass;
+        self.moments += body.moments();
+        let subdivision_index = S::subdivision_index(self.pivot, body.point_mass.position);
         let child = &mut self.subdivisions[subdivision_index];
 
         match child {
             Child::Empty => {
-                *child = Child::Body(body);
+                *child = Child::Leaf(Leaf::new(body));
             }
-            Child::Body(existing_body) => {
+            Child::Leaf(leaf) => {
                 let (width, pivot) =
                     S::subtree_width_pivot(subdivision_index, self.width, self.pivot);
-                if width < S::EPSILON {
-                    *existing_body += body;
+                if leaf.bodies.len() < leaf_capacity || width < S::EPSILON {
+                    leaf.push(body);
                     return;
                 }
                 let mut aggregate = MassAggregate::new(pivot, width);
-                aggregate.insert(*existing_body);
-                aggregate.insert(body);
+                for existing_body in leaf.bodies.drain(..) {
+                    aggregate.insert(existing_body, leaf_capacity);
+                }
+                aggregate.insert(body, leaf_capacity);
                 *child = Child::Aggregate(Box::new(aggregate));
             }
             Child::Aggregate(aggregate) => {
-                aggregate.insert(body);
+                aggregate.insert(body, leaf_capacity);
             }
         }
     }
 
-    pub fn estimate_net_g(
+    /// Collects the indices of the bodies under this node within `radius` of `centre`.
+    fn collect_within(&self, centre: S::Vector, radius: S::Scalar, found: &mut Vec<usize>) {
+        let radius_squared = radius * radius;
+        if S::cell_distance_squared(self.pivot, self.width, centre) > radius_squared {
+            return;
+        }
+        for child in &self.subdivisions {
+            match child {
+                Child::Empty => {}
+                Child::Leaf(leaf) => found.extend(
+                    leaf.bodies
+                        .iter()
+                        .filter(|body| {
+                            S::magnitude_squared(body.point_mass.position - centre)
+                                <= radius_squared
+                        })
+                        .map(|body| body.index),
+                ),
+                Child::Aggregate(aggregate) => aggregate.collect_within(centre, radius, found),
+            }
+        }
+    }
+
+    /// Merges the bodies under this node into `nearest`, the closest bodies to `centre` found so
+    /// far as `(distance_squared, index)` pairs in order of distance, keeping at most `k`.
+    fn collect_nearest(&self, centre: S::Vector, k: usize, nearest: &mut Vec<(S::Scalar, usize)>) {
+        let is_out_of_reach = |nearest: &Vec<(S::Scalar, usize)>, distance_squared| {
+            nearest.len() == k && distance_squared >= nearest[k - 1].0
+        };
+
+        // Visit the closest cells first, so that the rest can be ruled out sooner.
+        let mut children: Vec<_> = self
+            .subdivisions
+            .iter()
+            .enumerate()
+            .filter(|(_, child)| !matches!(child, Child::Empty))
+            .map(|(i, child)| {
+                let (width, pivot) = S::subtree_width_pivot(i, self.width, self.pivot);
+                (S::cell_distance_squared(pivot, width, centre), child)
+            })
+            .collect();
+        children.sort_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+        for (distance_squared, child) in children {
+            if is_out_of_reach(nearest, distance_squared) {
+                break;
+            }
+            match child {
+                Child::Empty => {}
+                Child::Leaf(leaf) => {
+                    for body in &leaf.bodies {
+                        let distance_squared =
+                            S::magnitude_squared(body.point_mass.position - centre);
+                        if !is_out_of_reach(nearest, distance_squared) {
+                            let position = nearest
+                                .iter()
+                                .position(|(other, _)| distance_squared < *other)
+                                .unwrap_or(nearest.len());
+                            nearest.insert(position, (distance_squared, body.index));
+                            nearest.truncate(k);
+                        }
+                    }
+                }
+                Child::Aggregate(aggregate) => aggregate.collect_nearest(centre, k, nearest),
+            }
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.subdivisions
+            .iter()
+            .all(|child| matches!(child, Child::Empty))
+    }
+
+    /// Moves the bodies under this node to their current positions and recomputes the totals
+    /// bottom-up.  Bodies which have left their cell are re-inserted at the lowest node whose cell
+    /// still contains them; those which have left this node's cell entirely are passed back in
+    /// `escaped`.
+    fn refit(
+        &mut self,
+        point_mass_of: &impl Fn(usize) -> PointMass<S>,
+        leaf_capacity: usize,
+        escaped: &mut Vec<Body<S>>,
+    ) {
+        let mut migrating = Vec::new();
+
+        for (i, child) in self.subdivisions.iter_mut().enumerate() {
+            match child {
+                Child::Empty => {}
+                Child::Leaf(leaf) => {
+                    let (width, pivot) = S::subtree_width_pivot(i, self.width, self.pivot);
+                    let mut total = PointMass::default();
+                    let mut moments = Moments::default();
+                    let sources = &mut leaf.sources;
+                    sources.clear();
+                    leaf.bodies.retain_mut(|body| {
+                        body.point_mass = point_mass_of(body.index);
+                        if S::cell_contains(pivot, width, body.point_mass.position) {
+                            total += body.point_mass;
+                            moments += body.moments();
+                            sources.extend([body.source()]);
+                            true
+                        } else {
+                            migrating.push(*body);
+                            false
+                        }
+                    });
+                    leaf.total = total;
+                    leaf.moments = moments;
+                    if leaf.bodies.is_empty() {
+                        *child = Child::Empty;
+                    }
+                }
+                Child::Aggregate(aggregate) => {
+                    aggregate.refit(point_mass_of, leaf_capacity, &mut migrating);
+                    if aggregate.is_empty() {
+                        *child = Child::Empty;
+                    }
+                }
+            }
+        }
+
+        for body in migrating {
+            if S::cell_contains(self.pivot, self.width, body.point_mass.position) {
+                self.insert(body, leaf_capacity);
+            } else {
+                escaped.push(body);
+            }
+        }
+
+        self.total = PointMass::default();
+        self.moments = Moments::default();
+        for child in &self.subdivisions {
+            self.total += child.total();
+            self.moments += child.moments();
+        }
+    }
+}
+
+impl<S, const NUM_SUBDIVISIONS: usize> MassAggregate<S, NUM_SUBDIVISIONS>
+where
+    S: DivisibleSpace<NUM_SUBDIVISIONS> + PeriodicSpace,
+{
+    /// Whether this node's total mass can stand in for its contents, as seen from anywhere within
+    /// `target_radius` of `target`.
+    fn can_approximate<L: ForceLaw<S>>(
+        &self,
+        target: S::Vector,
+        target_radius: S::Scalar,
+        walk: &Walk<S, L>,
+    ) -> bool {
+        if walk.opening.guard_inside_cell && S::cell_contains(self.pivot, self.width, target) {
+            return false;
+        }
+
+        let offset = walk.offset(target, self.total.position);
+        let distance_squared = if target_radius == S::SCALAR_ZERO {
+            S::magnitude_squared(offset)
+        } else {
+            let distance = S::magnitude(offset) - target_radius;
+            if distance <= S::SCALAR_ZERO {
+                return false;
+            }
+            distance * distance
+        };
+        let width_squared = self.width * self.width;
+
+        match walk.opening.criterion {
+            OpeningCriterion::BMax => {
+                let b_max = S::max_corner_distance(self.pivot, self.width, self.total.position);
+                b_max * b_max <= walk.theta_squared * distance_squared
+            }
+            OpeningCriterion::Relative if walk.previous_g > S::SCALAR_ZERO => {
+                walk.coupling_magnitude() * self.moments.absolute_strength() * width_squared
+                    <= walk.opening.relative_tolerance
+                        * walk.previous_g
+                        * distance_squared
+                        * distance_squared
+            }
+            OpeningCriterion::Geometric | OpeningCriterion::Relative => {
+                width_squared <= walk.theta_squared * distance_squared
+            }
+        }
+    }
+
+    fn estimate_net_g<L: ForceLaw<S>>(
         &self,
         other_position: S::Vector,
-        pivot: S::Vector,
-        width: S::Scalar,
-        theta_squared: S::Scalar,
-        grav_const: S::Scalar,
+        walk: &Walk<S, L>,
     ) -> S::Vector {
-        let to_self: S::Vector = self.total.position - other_position;
-        let distance_squared: S::Scalar = S::magnitude_squared(to_self);
-        if (width * width) <= theta_squared * distance_squared {
-            return self.total.g_at(other_position, grav_const);
+        // A cell holding the excluded body is always opened, so that it can be left out exactly.
+        let excluded = walk
+            .excluded
+            .filter(|&(_, position)| S::cell_contains(self.pivot, self.width, position));
+        if excluded.is_none() && self.can_approximate(other_position, S::SCALAR_ZERO, walk) {
+            return walk.g_from_moments(&self.moments, other_position);
         }
 
         let mut sum = S::VECTOR_ZERO;
 
-        self.subdivisions
-            .iter()
-            .enumerate()
-            .for_each(|(i, child)| match child {
+        self.subdivisions.iter().for_each(|child| match child {
+            Child::Empty => {}
+            Child::Leaf(leaf) => match excluded {
+                Some((index, _)) => {
+                    for body in leaf.bodies.iter().filter(|body| body.index != index) {
+                        sum += walk.g_from(&body.source(), other_position);
+                    }
+                }
+                None => sum += leaf.sources.g_at(other_position, walk),
+            },
+            Child::Aggregate(aggregate) => {
+                sum += aggregate.estimate_net_g(other_position, walk);
+            }
+        });
+        sum
+    }
+
+    fn find(&self, index: usize) -> Option<&Body<S>> {
+        self.subdivisions.iter().find_map(|child| match child {
+            Child::Empty => None,
+            Child::Leaf(leaf) => leaf.bodies.iter().find(|body| body.index == index),
+            Child::Aggregate(aggregate) => aggregate.find(index),
+        })
+    }
+
+    fn collect_leaves<'a>(&'a self, leaves: &mut Vec<&'a Leaf<S>>) {
+        for child in &self.subdivisions {
+            match child {
+                Child::Empty => {}
+                Child::Leaf(leaf) => leaves.push(leaf),
+                Child::Aggregate(aggregate) => aggregate.collect_leaves(leaves),
+            }
+        }
+    }
+
+    /// Builds the list of point masses which together approximate the field acting on every point
+    /// within `group_radius` of `group_centre`.
+    fn collect_interactions<L: ForceLaw<S>>(
+        &self,
+        group_centre: S::Vector,
+        group_radius: S::Scalar,
+        walk: &Walk<S, L>,
+        interactions: &mut Interactions<S>,
+    ) {
+        if self.can_approximate(group_centre, group_radius, walk) {
+            interactions.extend(self.moments.sources());
+            return;
+        }
+
+        for child in &self.subdivisions {
+            match child {
                 Child::Empty => {}
-                Child::Body(body) => {
-                    sum += body.g_at(other_position, grav_const);
+                Child::Leaf(leaf) => {
+                    interactions.extend(leaf.sources.sources());
                 }
                 Child::Aggregate(aggregate) => {
-                    let (width, pivot) = S::subtree_width_pivot(i, width, pivot);
-                    sum += aggregate.estimate_net_g(
-                        other_position,
-                        pivot,
-                        width,
-                        theta_squared,
-                        grav_const,
-                    );
+                    aggregate.collect_interactions(group_centre, group_radius, walk, interactions);
                 }
-            });
-        sum
+            }
+        }
     }
 }
 
 #[derive(Debug, Clone)]
-pub struct GravityField<S, const NUM_SUBDIVISIONS: usize>
+pub struct GravityField<S, const NUM_SUBDIVISIONS: usize, L = Newtonian>
 where
-    S: DivisibleSpace<NUM_SUBDIVISIONS>,
+    S: DivisibleSpace<NUM_SUBDIVISIONS> + PeriodicSpace,
+    L: ForceLaw<S>,
 {
-    origin: S::Vector,
-
     /// The length in each dimension of the space covered by this field.  At present this must be set large enough up-front.
     width: S::Scalar,
 
+    /// The number of bodies a leaf may hold before it is subdivided.
+    leaf_capacity: usize,
+
+    /// The law of the force between the bodies.  Their strengths must be set according to it.
+    pub law: L,
+    pub strategy: ForceStrategy,
+    pub opening: Opening<S>,
+    /// The periodic box the bodies live in, if any, in which case each body also attracts every
+    /// other through all its images.  The box must lie within the space covered by the field.
+    pub periodic: Option<PeriodicBox<S>>,
+
+    /// The magnitude of each body's acceleration at the previous step, for the relative opening
+    /// criterion.
+    previous_gs: Vec<S::Scalar>,
+
     root: MassAggregate<S, NUM_SUBDIVISIONS>,
 }
 
 impl GravityField2D {
+    /// The indices of the bodies inside `rect`, as of the last insert or refit.
+    pub fn bodies_in_rect(&self, rect: Rect) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.root.collect_in_rect(rect, &mut found);
+        found
+    }
+
     pub(crate) fn get_bounding_boxes(&self) -> Vec<Rect> {
-        let mut mass_aggregates = vec![self.root.clone()];
+        let mut mass_aggregates = vec![&self.root];
         let mut rects = Vec::new();
         while let Some(mass_aggreate) = mass_aggregates.pop() {
             rects.push(mass_aggreate.get_bounding_rect());
-            for child in mass_aggreate.subdivisions {
+            for child in &mass_aggreate.subdivisions {
                 match child {
                     Child::Empty => {}
-                    Child::Body(_) => {}
+                    Child::Leaf(_) => {}
                     Child::Aggregate(aggregate) => {
-                        mass_aggregates.push(*aggregate);
+                        mass_aggregates.push(aggregate);
                     }
                 }
             }
@@ -169,28 +766,110 @@ impl GravityField2D {
     }
 }
 
-impl<S, const NUM_SUBDIVISIONS: usize> GravityField<S, NUM_SUBDIVISIONS>
+impl<S, const NUM_SUBDIVISIONS: usize, L> GravityField<S, NUM_SUBDIVISIONS, L>
 where
-    S: DivisibleSpace<NUM_SUBDIVISIONS>,
+    S: DivisibleSpace<NUM_SUBDIVISIONS> + PeriodicSpace,
+    L: ForceLaw<S>,
 {
-    pub fn new(size: S::Scalar) -> Self {
+    pub fn new(size: S::Scalar, leaf_capacity: usize) -> Self {
         Self {
-            origin: S::VECTOR_ZERO,
             width: size,
+            leaf_capacity: at_least!(leaf_capacity, 1),
+            law: L::default(),
+            strategy: ForceStrategy::default(),
+            opening: Opening::default(),
+            periodic: None,
+            previous_gs: Vec::new(),
             root: MassAggregate::new(S::VECTOR_ZERO, size),
         }
     }
 
-    pub fn insert(&mut self, rhs: PointMass<S>) {
-        if rhs.mass == S::SCALAR_ZERO {
+    pub fn leaf_capacity(&self) -> usize {
+        self.leaf_capacity
+    }
+
+    pub fn insert(&mut self, index: usize, rhs: PointMass<S>) {
+        self.insert_body(Body::new(index, rhs));
+    }
+
+    pub fn insert_body(&mut self, body: Body<S>) {
+        if body.point_mass.mass == S::SCALAR_ZERO {
             return;
         }
-        if S::max_abs_dimension(rhs.position) >= self.width {
+        if S::max_abs_dimension(body.point_mass.position) >= self.width {
             // TODO!
-            warn!("PointMass out of bounds: {:?}", rhs);
+            warn!("PointMass out of bounds: {:?}", body.point_mass);
             return;
         }
-        self.root.insert(rhs);
+        self.root.insert(body, self.leaf_capacity);
+    }
+
+    /// Updates the field in place after the bodies have moved, rather than rebuilding it.
+    ///
+    /// `point_mass_of` gives the current state of the body inserted with each index.  Returns
+    /// `false` if any body has left the space covered by the field, in which case it has been
+    /// dropped and the field should be rebuilt with larger bounds.
+    pub fn refit(&mut self, point_mass_of: impl Fn(usize) -> PointMass<S>) -> bool {
+        let mut escaped = Vec::new();
+        self.root
+            .refit(&point_mass_of, self.leaf_capacity, &mut escaped);
+        escaped.is_empty()
+    }
+
+    /// The indices of the bodies within `radius` of `centre`, as of the last insert or refit.
+    pub fn bodies_within(&self, centre: S::Vector, radius: S::Scalar) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.root.collect_within(centre, radius, &mut found);
+        found
+    }
+
+    /// The position of the body with the given index as of the last insert or refit, if the field
+    /// holds it.
+    pub fn position_of(&self, index: usize) -> Option<S::Vector> {
+        self.root.find(index).map(|body| body.point_mass.position)
+    }
+
+    /// The indices of the `k` bodies nearest to `centre`, closest first, as of the last insert or
+    /// refit.  There may be fewer if the field holds fewer than `k` bodies.
+    pub fn nearest_bodies(&self, centre: S::Vector, k: usize) -> Vec<usize> {
+        if k == 0 {
+            return Vec::new();
+        }
+        let mut nearest = Vec::with_capacity(k + 1);
+        self.root.collect_nearest(centre, k, &mut nearest);
+        nearest.into_iter().map(|(_, index)| index).collect()
+    }
+
+    /// Records each body's acceleration at the previous step, indexed as the bodies are, for use by
+    /// the relative opening criterion.
+    pub fn set_previous_net_gs(&mut self, net_gs: impl IntoIterator<Item = S::Vector>) {
+        self.previous_gs.clear();
+        self.previous_gs
+            .extend(net_gs.into_iter().map(S::magnitude));
+    }
+
+    fn walk(
+        &self,
+        theta: S::Scalar,
+        grav_const: S::Scalar,
+        previous_g: S::Scalar,
+    ) -> Walk<'_, S, L> {
+        Walk {
+            law: self.law,
+            opening: self.opening,
+            theta_squared: theta * theta,
+            grav_const,
+            previous_g,
+            periodic: self.periodic.as_ref(),
+            excluded: None,
+        }
+    }
+
+    fn previous_g(&self, index: usize) -> S::Scalar {
+        self.previous_gs
+            .get(index)
+            .copied()
+            .unwrap_or(S::SCALAR_ZERO)
     }
 
     pub fn estimate_net_g(
@@ -200,15 +879,359 @@ where
         grav_const: S::Scalar,
     ) -> S::Vector {
         self.root
-            .estimate_net_g(at, self.origin, self.width, theta * theta, grav_const)
+            .estimate_net_g(at, &self.walk(theta, grav_const, S::SCALAR_ZERO))
+    }
+
+    /// The field at `at` of all the bodies but `excluded`, given as its index and its position
+    /// from `position_of`.  The cells holding it are opened rather than approximated, so that none
+    /// of its field is left behind.
+    pub fn estimate_net_g_excluding(
+        &self,
+        at: S::Vector,
+        excluded: (usize, S::Vector),
+        theta: S::Scalar,
+        grav_const: S::Scalar,
+    ) -> S::Vector {
+        let walk = Walk {
+            excluded: Some(excluded),
+            ..self.walk(theta, grav_const, S::SCALAR_ZERO)
+        };
+        self.root.estimate_net_g(at, &walk)
+    }
+
+    fn estimate_net_g_per_body(
+        &self,
+        positions: &[S::Vector],
+        theta: S::Scalar,
+        grav_const: S::Scalar,
+    ) -> Vec<S::Vector> {
+        let net_g = |(index, position): (usize, &S::Vector)| {
+            let walk = self.walk(theta, grav_const, self.previous_g(index));
+            self.root.estimate_net_g(*position, &walk)
+        };
+
+        #[cfg(feature = "rayon")]
+        {
+            use rayon::prelude::*;
+            positions.par_iter().enumerate().map(net_g).collect()
+        }
+        #[cfg(not(feature = "rayon"))]
+        {
+            positions.iter().enumerate().map(net_g).collect()
+        }
+    }
+
+    fn estimate_net_g_grouped(
+        &self,
+        positions: &[S::Vector],
+        theta: S::Scalar,
+        grav_const: S::Scalar,
+    ) -> Vec<S::Vector> {
+        let mut leaves = Vec::new();
+        self.root.collect_leaves(&mut leaves);
+
+        let walk_leaf = |leaf: &&Leaf<S>| {
+            // The group is only as tolerant of errors as its least accelerated body.
+            let previous_g = leaf
+                .bodies
+                .iter()
+                .map(|body| self.previous_g(body.index))
+                .reduce(|a, b| if b < a { b } else { a })
+                .unwrap_or(S::SCALAR_ZERO);
+            let walk = self.walk(theta, grav_const, previous_g);
+
+            let mut interactions = Interactions::default();
+            self.root.collect_interactions(
+                leaf.total.position,
+                leaf.radius(),
+                &walk,
+                &mut interactions,
+            );
+            leaf.apply_interactions(&interactions, &walk)
+        };
+
+        #[cfg(feature = "rayon")]
+        let leaf_results: Vec<_> = {
+            use rayon::prelude::*;
+            leaves.par_iter().map(walk_leaf).collect()
+        };
+        #[cfg(not(feature = "rayon"))]
+        let leaf_results: Vec<_> = leaves.iter().map(walk_leaf).collect();
+
+        let mut net_gs = vec![None; positions.len()];
+        for (index, g) in leaf_results.into_iter().flatten() {
+            if let Some(net_g) = net_gs.get_mut(index) {
+                *net_g = Some(g);
+            }
+        }
+        // Bodies which aren't in the tree, e.g. massless ones, still feel the field.
+        net_gs
+            .into_iter()
+            .zip(positions)
+            .map(|(net_g, position)| {
+                net_g.unwrap_or_else(|| self.estimate_net_g(*position, theta, grav_const))
+            })
+            .collect()
     }
 }
 
-impl<S, const NUM_SUBDIVISIONS: usize> AddAssign<PointMass<S>> for GravityField<S, NUM_SUBDIVISIONS>
+impl<S, const NUM_SUBDIVISIONS: usize, L> AddAssign<Body<S>>
+    for GravityField<S, NUM_SUBDIVISIONS, L>
 where
-    S: DivisibleSpace<NUM_SUBDIVISIONS>,
+    S: DivisibleSpace<NUM_SUBDIVISIONS> + PeriodicSpace,
+    L: ForceLaw<S>,
 {
-    fn add_assign(&mut self, rhs: PointMass<S>) {
-        self.insert(rhs);
+    fn add_assign(&mut self, rhs: Body<S>) {
+        self.insert_body(rhs);
+    }
+}
+
+impl<S, const NUM_SUBDIVISIONS: usize, L> GravitySolver<S> for GravityField<S, NUM_SUBDIVISIONS, L>
+where
+    S: DivisibleSpace<NUM_SUBDIVISIONS> + PeriodicSpace,
+    L: ForceLaw<S>,
+{
+    fn estimate_net_g_all(
+        &self,
+        positions: &[S::Vector],
+        theta: S::Scalar,
+        grav_const: S::Scalar,
+    ) -> Vec<S::Vector> {
+        match self.strategy {
+            ForceStrategy::PerBody => self.estimate_net_g_per_body(positions, theta, grav_const),
+            ForceStrategy::GroupWalk => self.estimate_net_g_grouped(positions, theta, grav_const),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nannou::geom::pt2;
+
+    use super::*;
+
+    fn spiral(n: usize, turn: f32) -> Vec<PointMass<Space2D>> {
+        (0..n)
+            .map(|i| {
+                let r = 1.0 + i as f32;
+                let angle = i as f32 * 0.7 + turn;
+                PointMass::new(pt2(r * angle.cos(), r * angle.sin()), 1.0 + (i % 3) as f32)
+            })
+            .collect()
+    }
+
+    fn field_of(bodies: &[PointMass<Space2D>]) -> GravityField2D {
+        bucketed_field_of(bodies, 1)
+    }
+
+    fn bucketed_field_of(bodies: &[PointMass<Space2D>], leaf_capacity: usize) -> GravityField2D {
+        let mut field = GravityField2D::new(1024.0, leaf_capacity);
+        for (index, body) in bodies.iter().enumerate() {
+            field.insert(index, *body);
+        }
+        field
+    }
+
+    #[test]
+    fn test_refit_matches_rebuild() {
+        let mut field = field_of(&spiral(200, 0.0));
+        let moved = spiral(200, 0.3);
+        assert!(field.refit(|index| moved[index]));
+
+        let rebuilt = field_of(&moved);
+        for probe in [pt2(0.0, 0.0), pt2(50.0, -20.0), pt2(-300.0, 150.0)] {
+            let refitted_g: Point2 = field.estimate_net_g(probe, 0.0, 1.0);
+            let rebuilt_g: Point2 = rebuilt.estimate_net_g(probe, 0.0, 1.0);
+            assert!((refitted_g - rebuilt_g).length() <= 1e-4 * rebuilt_g.length());
+        }
+        assert!((field.root.total.mass - rebuilt.root.total.mass).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_refit_reports_escaped_bodies() {
+        let bodies = spiral(10, 0.0);
+        let mut field = field_of(&bodies);
+        assert!(!field
+            .refit(|index| PointMass::new(bodies[index].position * 1000.0, bodies[index].mass)));
+    }
+
+    #[test]
+    fn test_group_walk_matches_per_body() {
+        let bodies = spiral(300, 0.0);
+        let positions: Vec<Point2> = bodies.iter().map(|body| body.position).collect();
+        let exact = field_of(&bodies).estimate_net_g_all(&positions, 0.0, 1.0);
+
+        let mut field = bucketed_field_of(&bodies, 8);
+        field.strategy = ForceStrategy::GroupWalk;
+        for (theta, tolerance) in [(0.0, 1e-4), (0.5, 5e-2)] {
+            let grouped = field.estimate_net_g_all(&positions, theta, 1.0);
+            for (exact_g, grouped_g) in exact.iter().zip(&grouped) {
+                assert!((*exact_g - *grouped_g).length() <= tolerance * exact_g.length());
+            }
+        }
+    }
+
+    #[test]
+    fn test_leaf_kernel_matches_pairwise_sum() {
+        let sums = LeafSums::new(spiral(500, 0.0).iter().map(|s| (s.position, s.mass)));
+        for target in spiral(50, 0.5) {
+            let (pairwise, kernel) = (sums.pairwise(target.position), sums.kernel(target.position));
+            assert!((pairwise - kernel).length() <= 1e-4 * pairwise.length());
+        }
+    }
+
+    #[test]
+    fn test_excluded_body_leaves_no_field() {
+        // The excluded body is heavy enough that any of its field left behind, including that of
+        // its periodic images, would swamp that of the others.
+        let mut bodies = spiral(300, 0.0);
+        bodies[150].mass = 1e6;
+        let mut others = bodies.clone();
+        others.remove(150);
+
+        for periodic in [None, Some(PeriodicBox::new(1024.0))] {
+            let mut field = bucketed_field_of(&bodies, 4);
+            let mut exact = field_of(&others);
+            field.periodic = periodic.clone();
+            exact.periodic = periodic;
+
+            let excluded = (150, field.position_of(150).unwrap());
+            assert_eq!(excluded.1, bodies[150].position);
+            for target in spiral(40, 0.5) {
+                let expected = exact.estimate_net_g(target.position, 0.0, 1.0);
+                let estimated = field.estimate_net_g_excluding(target.position, excluded, 0.5, 1.0);
+                assert!((estimated - expected).length() <= 5e-2 * expected.length());
+            }
+        }
+    }
+
+    #[test]
+    fn test_opening_criteria_match_direct_sum() {
+        let bodies = spiral(300, 0.0);
+        let positions: Vec<Point2> = bodies.iter().map(|body| body.position).collect();
+        let mut field = field_of(&bodies);
+        let exact = field.estimate_net_g_all(&positions, 0.0, 1.0);
+        field.set_previous_net_gs(exact.iter().copied());
+
+        for criterion in [
+            OpeningCriterion::Geometric,
+            OpeningCriterion::BMax,
+            OpeningCriterion::Relative,
+        ] {
+            field.opening = Opening {
+                criterion,
+                guard_inside_cell: true,
+                relative_tolerance: 0.01,
+            };
+            let estimated = field.estimate_net_g_all(&positions, 0.35, 1.0);
+            let error_squared: f32 = exact
+                .iter()
+                .zip(&estimated)
+                .map(|(e, g)| (*e - *g).length_squared() / e.length_squared())
+                .sum();
+            let rms_relative_error = (error_squared / exact.len() as f32).sqrt();
+            assert!(
+                rms_relative_error <= 2e-2,
+                "{criterion:?}: rms relative error {rms_relative_error}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_relative_criterion_under_coulomb() {
+        // Light but strongly charged dipoles, whose cells a criterion based on their masses would
+        // accept from anywhere.
+        let bodies = spiral(300, 0.0);
+        let mut field = GravityField2D::new(1024.0, 1);
+        field.law = ForceLaw2D::Coulomb;
+        let mut positions = Vec::new();
+        for (i, body) in bodies.iter().enumerate() {
+            let (position, partner) = (body.position, body.position + vec2(5.0, 0.0));
+            field.insert_body(Body::with_strength(
+                2 * i,
+                PointMass::new(position, 1e-3),
+                1.0,
+            ));
+            field.insert_body(Body::with_strength(
+                2 * i + 1,
+                PointMass::new(partner, 1e-3),
+                -1.0,
+            ));
+            positions.extend([position, partner]);
+        }
+        let exact = field.estimate_net_g_all(&positions, 0.0, 1.0);
+        field.set_previous_net_gs(exact.iter().copied());
+
+        field.opening = Opening {
+            criterion: OpeningCriterion::Relative,
+            guard_inside_cell: true,
+            relative_tolerance: 0.01,
+        };
+        let estimated = field.estimate_net_g_all(&positions, 0.35, 1.0);
+        let error_squared: f32 = exact
+            .iter()
+            .zip(&estimated)
+            .map(|(e, g)| (*e - *g).length_squared() / e.length_squared())
+            .sum();
+        let rms_relative_error = (error_squared / exact.len() as f32).sqrt();
+        assert!(
+            rms_relative_error <= 2e-2,
+            "rms relative error {rms_relative_error}"
+        );
+    }
+
+    #[test]
+    fn test_spatial_queries_match_brute_force() {
+        let bodies = spiral(300, 0.0);
+        let field = bucketed_field_of(&bodies, 4);
+        let sorted = |mut indices: Vec<usize>| {
+            indices.sort_unstable();
+            indices
+        };
+
+        let rect = Rect::from_xy_wh(pt2(40.0, -25.0), vec2(120.0, 90.0));
+        let in_rect = (0..bodies.len()).filter(|&i| rect.contains(bodies[i].position));
+        assert_eq!(
+            sorted(field.bodies_in_rect(rect)),
+            in_rect.collect::<Vec<_>>()
+        );
+
+        let centre = pt2(-60.0, 35.0);
+        let distance = |i: usize| (bodies[i].position - centre).length();
+        let within = (0..bodies.len()).filter(|&i| distance(i) <= 80.0);
+        assert_eq!(
+            sorted(field.bodies_within(centre, 80.0)),
+            within.collect::<Vec<_>>()
+        );
+
+        let mut by_distance: Vec<usize> = (0..bodies.len()).collect();
+        by_distance.sort_by(|&a, &b| distance(a).partial_cmp(&distance(b)).unwrap());
+        assert_eq!(field.nearest_bodies(centre, 10), by_distance[..10]);
+        assert_eq!(field.nearest_bodies(centre, 1000).len(), bodies.len());
+    }
+
+    #[test]
+    fn test_coulomb_dipoles_match_direct_sum() {
+        // Aligned dipoles, so that every cell is neutral but the field doesn't vanish.
+        let bodies = spiral(300, 0.0);
+        let mut field = GravityField2D::new(1024.0, 4);
+        field.law = ForceLaw2D::Coulomb;
+        for (i, body) in bodies.iter().enumerate() {
+            let partner = PointMass::new(body.position + vec2(5.0, 0.0), body.mass);
+            field.insert_body(Body::with_strength(2 * i, *body, 1.0));
+            field.insert_body(Body::with_strength(2 * i + 1, partner, -1.0));
+        }
+
+        let probes = [pt2(700.0, 0.0), pt2(-400.0, 500.0), pt2(20.0, -30.0)];
+        for probe in probes {
+            let exact = field.estimate_net_g(probe, 0.0, 1.0);
+            let approximate = field.estimate_net_g(probe, 0.3, 1.0);
+            assert!(
+                (approximate - exact).length() <= 0.05 * exact.length(),
+                "{:?} vs {:?}",
+                approximate,
+                exact
+            );
+        }
     }
 }
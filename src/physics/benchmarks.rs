@@ -0,0 +1,49 @@
+//! Fixtures for the benchmarks in `benches/`, which compare the particle store and the leaf kernel
+//! with the layouts they replaced.
+
+use nannou::prelude::*;
+
+pub use super::barnes_hut::LeafSums;
+use super::particle::Particle;
+use super::particles::Particles;
+
+/// The same particles stored both as an array of structs, as they used to be, and as the struct of
+/// arrays which replaced it, with a constant acceleration for each.
+pub struct Integration {
+    rows: Vec<Particle>,
+    columns: Particles,
+    accelerations: Vec<Vec2>,
+}
+
+impl Integration {
+    /// `count` random particles, a quarter of them gas.
+    pub fn new(count: usize) -> Self {
+        let rows: Vec<Particle> = (0..count)
+            .map(|i| match i % 4 {
+                0 => Particle::new_random_gas(),
+                _ => Particle::new_random(),
+            })
+            .collect();
+        Self {
+            columns: rows.iter().copied().collect(),
+            rows,
+            accelerations: vec![vec2(1.0, -1.0); count],
+        }
+    }
+
+    /// Integrates the array of structs by `dt`, as `Particles::integrate` does the struct of arrays.
+    pub fn step_rows(&mut self, dt: f32) {
+        for (particle, &acceleration) in self.rows.iter_mut().zip(&self.accelerations) {
+            particle.acceleration = acceleration;
+            particle.velocity += acceleration * dt;
+            particle.position += particle.velocity * dt;
+            if let Some(gas) = particle.tag.gas_mut() {
+                gas.internal_energy = (gas.internal_energy + gas.energy_rate * dt).max(0.0);
+            }
+        }
+    }
+
+    pub fn step_columns(&mut self, dt: f32) {
+        self.columns.integrate(dt, &self.accelerations);
+    }
+}
@@ -0,0 +1,90 @@
+use nannou::prelude::*;
+
+use super::particle::Particle;
+
+/// The properties of a Hookean spring.
+#[derive(Debug, Clone, Copy, PartialEq, Derivative)]
+#[derivative(Default)]
+pub struct Spring {
+    /// The force per unit extension.
+    #[derivative(Default(value = "100.0"))]
+    pub stiffness: f32,
+    /// The force per unit speed at which the ends move apart.
+    #[derivative(Default(value = "1.0"))]
+    pub damping: f32,
+    /// The extension or compression, as a fraction of the rest length, beyond which the spring
+    /// breaks.  Zero for an unbreakable spring.
+    #[derivative(Default(value = "0.5"))]
+    pub breaking_strain: f32,
+}
+
+/// A spring between two particles, identified by their indices.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) struct Bond {
+    pub a: usize,
+    pub b: usize,
+    pub rest_length: f32,
+    pub spring: Spring,
+}
+
+impl Bond {
+    /// The force on `a`, which is opposite to that on `b`, or `None` if the bond breaks.  `offset`
+    /// is from `a` to `b`, or to its nearest image in a periodic universe.
+    pub fn force(&self, a: &Particle, b: &Particle, offset: Vec2) -> Option<Vec2> {
+        let length = offset.length();
+        let extension = length - self.rest_length;
+        if self.spring.breaking_strain > 0.0
+            && extension.abs() > self.spring.breaking_strain * self.rest_length
+        {
+            return None;
+        }
+        let direction = if length > 0.0 {
+            offset / length
+        } else {
+            Vec2::ZERO
+        };
+        let separation_speed = (b.velocity - a.velocity).dot(direction);
+        Some(
+            direction
+                * (self.spring.stiffness * extension + self.spring.damping * separation_speed),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spring() {
+        let (a, mut b) = (Particle::new(pt2(0.0, 0.0)), Particle::new(pt2(12.0, 0.0)));
+        let bond = Bond {
+            a: 0,
+            b: 1,
+            rest_length: 10.0,
+            spring: Spring::default(),
+        };
+        // Stretched springs pull their ends together...
+        assert_eq!(
+            bond.force(&a, &b, b.position - a.position),
+            Some(vec2(200.0, 0.0))
+        );
+        // ...more so while the ends are moving apart.
+        b.velocity = vec2(10.0, 0.0);
+        assert_eq!(
+            bond.force(&a, &b, b.position - a.position),
+            Some(vec2(210.0, 0.0))
+        );
+
+        b.position = pt2(16.0, 0.0);
+        assert_eq!(bond.force(&a, &b, b.position - a.position), None);
+        let unbreakable = Bond {
+            spring: Spring {
+                breaking_strain: 0.0,
+                ..Spring::default()
+            },
+            ..bond
+        };
+        assert!(unbreakable.force(&a, &b, b.position - a.position).is_some());
+    }
+}
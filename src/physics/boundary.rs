@@ -0,0 +1,73 @@
+use nannou::prelude::*;
+
+use super::space::PeriodicSpace;
+use super::space_2d::Space2D;
+
+/// What happens to particles which reach the edge of the universe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Derivative)]
+#[derivative(Default)]
+pub enum BoundaryMode {
+    /// Particles keep going, until they're too far away to be included in the gravity field.
+    #[derivative(Default)]
+    None,
+    /// Particles bounce elastically off the walls.
+    Reflecting,
+    /// Particles are removed from the universe.
+    Absorbing,
+    /// Particles leaving through one wall re-enter through the opposite one.
+    Wrap,
+}
+
+/// A square boundary centred on the origin.
+#[derive(Debug, Clone, Copy, Derivative)]
+#[derivative(Default)]
+pub struct Boundary {
+    pub mode: BoundaryMode,
+    #[derivative(Default(value = "2000.0"))]
+    pub size: f32,
+}
+
+impl Boundary {
+    pub fn rect(&self) -> Rect {
+        Rect::from_w_h(self.size, self.size)
+    }
+
+    /// The offset from `from` to `to`, or to its nearest image if the boundary wraps.
+    pub(super) fn offset(&self, from: Point2, to: Point2) -> Vec2 {
+        match self.mode {
+            BoundaryMode::Wrap => Space2D::nearest_image(to - from, self.size),
+            _ => to - from,
+        }
+    }
+
+    /// Applies the boundary to a particle which has just been updated, returning `false` if it has
+    /// been absorbed.
+    pub(super) fn apply(&self, position: &mut Point2, velocity: &mut Vec2) -> bool {
+        let half_size = self.size / 2.0;
+        match self.mode {
+            BoundaryMode::None => true,
+            BoundaryMode::Reflecting => {
+                reflect(&mut position.x, &mut velocity.x, half_size);
+                reflect(&mut position.y, &mut velocity.y, half_size);
+                true
+            }
+            BoundaryMode::Absorbing => {
+                position.x.abs() <= half_size && position.y.abs() <= half_size
+            }
+            BoundaryMode::Wrap => {
+                *position -= (*position / self.size).round() * self.size;
+                true
+            }
+        }
+    }
+}
+
+/// Reflects one component of a particle's motion off the walls at `±half_size`.
+fn reflect(position: &mut f32, velocity: &mut f32, half_size: f32) {
+    if position.abs() > half_size {
+        let wall = half_size.copysign(*position);
+        // Clamped in case the particle has crossed the whole box in one step.
+        *position = (2.0 * wall - *position).clamp(-half_size, half_size);
+        *velocity = -*velocity;
+    }
+}
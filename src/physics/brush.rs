@@ -0,0 +1,169 @@
+use nannou::prelude::*;
+use nannou::rand::{thread_rng, Rng};
+use rand_distr::Normal;
+
+use super::particle::Particle;
+
+/// The distribution of particles a brush paints.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Derivative)]
+#[derivative(Default)]
+pub enum BrushShape {
+    /// A Gaussian cluster whose standard deviation is the brush size.
+    #[derivative(Default)]
+    Cluster,
+    /// A uniform disk of radius the brush size, rotating at the circular velocity due to the mass
+    /// inside each particle's radius.
+    Disk,
+    /// A uniform square with sides of the brush size.
+    Square,
+    /// A line of the brush size along the bulk velocity, or horizontal if there is none.
+    Stream,
+}
+
+/// Paints groups of particles into a universe.
+#[derive(Debug, Clone, Copy, PartialEq, Derivative)]
+#[derivative(Default)]
+pub struct Brush {
+    pub shape: BrushShape,
+    #[derivative(Default(value = "200"))]
+    pub count: usize,
+    #[derivative(Default(value = "100.0"))]
+    pub size: f32,
+    /// The masses are drawn from the power law `dN/dm ∝ m^-mass_slope` between `min_mass` and
+    /// `max_mass`.
+    #[derivative(Default(value = "1.0"))]
+    pub min_mass: f32,
+    #[derivative(Default(value = "30.0"))]
+    pub max_mass: f32,
+    #[derivative(Default(value = "2.35"))]
+    pub mass_slope: f32,
+    pub velocity: Vec2,
+    /// Adds the velocity of a circular orbit about the nearest body at least `orbit_min_mass`.
+    pub orbit_nearest: bool,
+    #[derivative(Default(value = "100.0"))]
+    pub orbit_min_mass: f32,
+}
+
+impl Brush {
+    /// The particles painted at `centre`, orbiting `central` if it's given as the position,
+    /// velocity and mass of a body.
+    pub(super) fn paint(
+        &self,
+        centre: Point2,
+        grav_const: f32,
+        central: Option<(Point2, Vec2, f32)>,
+    ) -> Vec<Particle> {
+        let mut particles: Vec<Particle> = (0..self.count)
+            .map(|_| {
+                let mass = self.sample_mass();
+                Particle {
+                    velocity: self.velocity,
+                    mass,
+                    radius: mass.cbrt(),
+                    ..Particle::new(centre + self.sample_offset())
+                }
+            })
+            .collect();
+
+        if self.shape == BrushShape::Disk {
+            let mut by_radius: Vec<usize> = (0..particles.len()).collect();
+            let radius = |i: usize| (particles[i].position - centre).length();
+            by_radius.sort_by(|&a, &b| radius(a).total_cmp(&radius(b)));
+            let mut enclosed = 0.0;
+            for i in by_radius {
+                let offset = particles[i].position - centre;
+                let distance = offset.length();
+                if distance > 0.0 {
+                    let speed = (grav_const * enclosed / distance).sqrt();
+                    particles[i].velocity += offset.perp() / distance * speed;
+                }
+                enclosed += particles[i].mass;
+            }
+        }
+        if let Some((position, velocity, mass)) = central {
+            for particle in &mut particles {
+                particle.velocity +=
+                    velocity + circular_velocity(particle.position - position, mass, grav_const);
+            }
+        }
+        particles
+    }
+
+    fn sample_offset(&self) -> Vec2 {
+        let mut rng = thread_rng();
+        match self.shape {
+            BrushShape::Cluster => {
+                let normal = Normal::new(0.0, self.size).unwrap();
+                vec2(rng.sample(normal), rng.sample(normal))
+            }
+            BrushShape::Disk => {
+                let radius = self.size * rng.gen::<f32>().sqrt();
+                let angle = rng.gen_range(0.0..TAU);
+                vec2(angle.cos(), angle.sin()) * radius
+            }
+            BrushShape::Square => vec2(rng.gen::<f32>() - 0.5, rng.gen::<f32>() - 0.5) * self.size,
+            BrushShape::Stream => {
+                let direction = self.velocity.try_normalize().unwrap_or(Vec2::X);
+                direction * ((rng.gen::<f32>() - 0.5) * self.size)
+            }
+        }
+    }
+
+    /// A mass drawn from the power law spectrum by inverting its cumulative distribution.
+    fn sample_mass(&self) -> f32 {
+        let (low, high) = (
+            self.min_mass.max(f32::EPSILON),
+            self.max_mass.max(self.min_mass),
+        );
+        let u = thread_rng().gen::<f32>();
+        let exponent = 1.0 - self.mass_slope;
+        if exponent.abs() < 1e-3 {
+            low * (high / low).powf(u)
+        } else {
+            (low.powf(exponent) + u * (high.powf(exponent) - low.powf(exponent)))
+                .powf(1.0 / exponent)
+        }
+    }
+}
+
+/// The velocity of an anticlockwise circular orbit at `offset` from a body of `mass`.
+fn circular_velocity(offset: Vec2, mass: f32, grav_const: f32) -> Vec2 {
+    let distance = offset.length();
+    if distance == 0.0 {
+        return Vec2::ZERO;
+    }
+    offset.perp() / distance * (grav_const * mass / distance).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_painted_particles() {
+        let brush = Brush {
+            shape: BrushShape::Square,
+            velocity: vec2(5.0, 0.0),
+            orbit_nearest: true,
+            ..Brush::default()
+        };
+        let centre = pt2(300.0, 0.0);
+        let particles = brush.paint(centre, 1.0, Some((Point2::ZERO, vec2(0.0, 1.0), 270_000.0)));
+
+        assert_eq!(particles.len(), brush.count);
+        for particle in &particles {
+            assert!((brush.min_mass..=brush.max_mass).contains(&particle.mass));
+            let offset = particle.position - centre;
+            assert!(offset.x.abs() <= 50.0 && offset.y.abs() <= 50.0);
+        }
+        // At the centre, the orbit adds (0, 30) to the bulk and central body's velocities.
+        let nearest = particles
+            .iter()
+            .min_by(|a, b| {
+                let distance = |p: &Particle| (p.position - centre).length();
+                distance(a).total_cmp(&distance(b))
+            })
+            .unwrap();
+        assert!((nearest.velocity - vec2(5.0, 31.0)).length() < 5.0);
+    }
+}
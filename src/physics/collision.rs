@@ -0,0 +1,102 @@
+use super::particle::Particle;
+
+/// What happens when two particles' radii overlap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Derivative)]
+#[derivative(Default)]
+pub enum CollisionMode {
+    /// Particles pass through each other.
+    #[derivative(Default)]
+    None,
+    /// Particles coalesce into one, conserving mass and momentum.
+    Merge,
+    /// Particles bounce off each other, losing a fraction of their relative speed set by the
+    /// coefficient of restitution.
+    Bounce,
+}
+
+pub(super) fn overlapping(a: &Particle, b: &Particle) -> bool {
+    let reach = a.radius + b.radius;
+    (b.position - a.position).length_squared() < reach * reach
+}
+
+/// Merges `b` into `a` at their centre of mass, conserving mass and momentum.  The merged
+/// particle's radius grows as if the two were spheres of equal density, unless `a` is a black hole,
+/// which keeps its capture radius.
+pub(super) fn merge(a: &mut Particle, b: &Particle) {
+    let mass = a.mass + b.mass;
+    if mass > 0.0 {
+        a.position = (a.position * a.mass + b.position * b.mass) / mass;
+        a.velocity = (a.velocity * a.mass + b.velocity * b.mass) / mass;
+    }
+    a.mass = mass;
+    if a.capture_radius().is_none() {
+        a.radius = (a.radius.powi(3) + b.radius.powi(3)).cbrt();
+    }
+}
+
+/// Exchanges momentum between two overlapping particles along the line between their centres, if
+/// they're approaching each other.  A `restitution` of one is perfectly elastic and zero perfectly
+/// inelastic.
+pub(super) fn bounce(a: &mut Particle, b: &mut Particle, restitution: f32) {
+    let normal = (b.position - a.position).normalize_or_zero();
+    let approach_speed = (b.velocity - a.velocity).dot(normal);
+    if approach_speed >= 0.0 || a.mass <= 0.0 || b.mass <= 0.0 {
+        return;
+    }
+    let impulse = -(1.0 + restitution) * approach_speed / (1.0 / a.mass + 1.0 / b.mass);
+    a.velocity -= normal * (impulse / a.mass);
+    b.velocity += normal * (impulse / b.mass);
+}
+
+#[cfg(test)]
+mod tests {
+    use nannou::geom::{pt2, vec2};
+
+    use super::*;
+
+    fn particle(x: f32, vx: f32, mass: f32) -> Particle {
+        let mut particle = Particle::new(pt2(x, 0.0));
+        particle.velocity = vec2(vx, 0.0);
+        particle.mass = mass;
+        particle
+    }
+
+    #[test]
+    fn test_merge_conserves_mass_and_momentum() {
+        let mut a = particle(0.0, 10.0, 1.0);
+        let b = particle(4.0, -2.0, 3.0);
+        merge(&mut a, &b);
+        assert_eq!(a.mass, 4.0);
+        assert!((a.position.x - 3.0).abs() < 1e-6);
+        assert!((a.velocity.x * a.mass - (10.0 - 6.0)).abs() < 1e-5);
+        assert!(a.radius > b.radius);
+    }
+
+    #[test]
+    fn test_black_holes_keep_their_capture_radius() {
+        let mut black_hole = Particle::new_black_hole(pt2(0.0, 0.0), vec2(0.0, 0.0), 10.0, 8.0);
+        merge(&mut black_hole, &particle(4.0, 10.0, 10.0));
+        assert_eq!(black_hole.mass, 20.0);
+        assert_eq!(black_hole.velocity.x, 5.0);
+        assert_eq!(black_hole.capture_radius(), Some(8.0));
+        assert_eq!(black_hole.radius, 8.0);
+    }
+
+    #[test]
+    fn test_bounce() {
+        let kinetic_energy = |a: &Particle, b: &Particle| {
+            a.mass * a.velocity.length_squared() + b.mass * b.velocity.length_squared()
+        };
+        let (mut a, mut b) = (particle(0.0, 10.0, 1.0), particle(4.0, -2.0, 3.0));
+        let energy = kinetic_energy(&a, &b);
+        bounce(&mut a, &mut b, 1.0);
+        assert!((kinetic_energy(&a, &b) - energy).abs() < 1e-3 * energy);
+        assert!((a.velocity.x + 3.0 * b.velocity.x - 4.0).abs() < 1e-4);
+        assert!(b.velocity.x > a.velocity.x);
+
+        // Perfectly inelastic collisions leave the particles moving together.
+        let (mut a, mut b) = (particle(0.0, 10.0, 1.0), particle(4.0, -2.0, 3.0));
+        bounce(&mut a, &mut b, 0.0);
+        assert!((a.velocity.x - b.velocity.x).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,90 @@
+/// A Friedmann-Lemaître-Robertson-Walker background, against which the particles' positions are
+/// comoving coordinates.
+///
+/// Gravity between the particles is weakened by `a^-3` in comoving coordinates, and their
+/// comoving velocities decay under the Hubble drag `-2 H v`, where `a` is the scale factor and `H`
+/// the Hubble parameter.  Pairs naturally with a periodic box, whose Ewald summation removes the
+/// pull of the mean density.
+///
+/// External potentials and post-Newtonian corrections are evaluated at physical positions, while
+/// gas pressure, bonds, drag and dynamical friction are switched off.
+#[derive(Debug, Clone, Copy, PartialEq, Derivative)]
+#[derivative(Default)]
+pub struct Cosmology {
+    /// The density of matter today, as a fraction of the critical density.
+    #[derivative(Default(value = "0.3"))]
+    pub omega_matter: f32,
+    /// The density of dark energy, as a fraction of the critical density.
+    #[derivative(Default(value = "0.7"))]
+    pub omega_lambda: f32,
+    /// The Hubble constant, in inverse simulation units of time.
+    #[derivative(Default(value = "0.05"))]
+    pub hubble_constant: f32,
+    /// The scale factor, which is one today.
+    #[derivative(Default(value = "1.0"))]
+    pub scale_factor: f32,
+}
+
+impl Cosmology {
+    /// A cosmology starting at the given redshift.
+    pub fn starting_at(redshift: f32) -> Self {
+        Self {
+            scale_factor: 1.0 / (1.0 + redshift),
+            ..Self::default()
+        }
+    }
+
+    pub fn redshift(&self) -> f32 {
+        1.0 / self.scale_factor - 1.0
+    }
+
+    /// The Hubble parameter at the current scale factor.
+    pub fn hubble_parameter(&self) -> f32 {
+        self.hubble_parameter_at(self.scale_factor)
+    }
+
+    fn hubble_parameter_at(&self, scale_factor: f32) -> f32 {
+        let omega_curvature = 1.0 - self.omega_matter - self.omega_lambda;
+        let squared = self.omega_matter / scale_factor.powi(3)
+            + omega_curvature / scale_factor.powi(2)
+            + self.omega_lambda;
+        self.hubble_constant * squared.max(0.0).sqrt()
+    }
+
+    /// The factor by which the gravity between the particles is scaled in comoving coordinates.
+    pub(super) fn gravity_scale(&self) -> f32 {
+        self.scale_factor.powi(-3)
+    }
+
+    /// Advances the scale factor by `dt` with the midpoint method.
+    pub(super) fn advance(&mut self, dt: f32) {
+        let a = self.scale_factor;
+        let midpoint = a + 0.5 * dt * a * self.hubble_parameter_at(a);
+        self.scale_factor = a + dt * midpoint * self.hubble_parameter_at(midpoint);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_einstein_de_sitter_expansion() {
+        // A flat, matter dominated universe expands as t^(2/3), reaching a = 1 at t = 2 / (3 H0).
+        let mut cosmology = Cosmology {
+            omega_matter: 1.0,
+            omega_lambda: 0.0,
+            hubble_constant: 1.0,
+            ..Cosmology::starting_at(9.0)
+        };
+        let age = |a: f32| 2.0 / 3.0 * a.powf(1.5);
+        let steps = 10_000;
+        let dt = (age(1.0) - age(cosmology.scale_factor)) / steps as f32;
+        for _ in 0..steps {
+            cosmology.advance(dt);
+        }
+        assert!((cosmology.scale_factor - 1.0).abs() < 1e-3);
+        assert!(cosmology.redshift().abs() < 1e-3);
+        assert!((cosmology.hubble_parameter() - 1.0).abs() < 2e-3);
+    }
+}
@@ -0,0 +1,268 @@
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use nannou::geom::{vec2, Vec2};
+
+use crate::physics::space::PeriodicSpace;
+
+/// The number of table cells along each side of the quadrant covered by an `EwaldTable2D`.
+const RESOLUTION: usize = 64;
+/// The number of periodic images summed over in each direction in real space.
+const REAL_SPACE_IMAGES: i32 = 3;
+/// The number of wave vectors summed over in each direction in reciprocal space.
+const RECIPROCAL_SPACE_WAVES: i32 = 4;
+
+/// A square periodic box centred on the origin, with the Ewald corrections for its size.
+#[derive(Debug)]
+pub struct PeriodicBox<S: PeriodicSpace> {
+    pub size: S::Scalar,
+    ewald_table: Arc<S::EwaldTable>,
+}
+
+// Derived, this would require the table itself to be `Clone`.
+impl<S: PeriodicSpace> Clone for PeriodicBox<S> {
+    fn clone(&self) -> Self {
+        Self {
+            size: self.size,
+            ewald_table: Arc::clone(&self.ewald_table),
+        }
+    }
+}
+
+impl<S: PeriodicSpace> PeriodicBox<S> {
+    pub fn new(size: S::Scalar) -> Self {
+        Self {
+            size,
+            ewald_table: Arc::new(S::ewald_table(size)),
+        }
+    }
+
+    /// The offset from `from` to the nearest image of `to`.
+    pub fn offset(&self, from: S::Vector, to: S::Vector) -> S::Vector {
+        S::nearest_image(to - from, self.size)
+    }
+
+    /// The position equivalent to `position` inside the box.
+    pub fn wrap(&self, position: S::Vector) -> S::Vector {
+        S::nearest_image(position, self.size)
+    }
+
+    /// The correction to the field of a unit source whose nearest image is at `offset` from the
+    /// target.
+    pub fn ewald_correction(&self, offset: S::Vector) -> S::Vector {
+        S::ewald_correction(&self.ewald_table, offset)
+    }
+}
+
+/// Corrections to the inverse-square field of the nearest image of a unit source in a square
+/// periodic box, accounting for the contributions of all its other images, found by Ewald
+/// summation.
+///
+/// The bodies lie in a plane which is periodic in both its dimensions, so this is the quasi-2D
+/// form of the Ewald sum.  As in cosmological codes, the mean density is taken to be balanced by
+/// a uniform background.  The corrections are tabulated over one quadrant of the box and
+/// interpolated, using their symmetry to cover the others.
+#[derive(Debug, Clone)]
+pub struct EwaldTable2D {
+    box_size: f32,
+    corrections: Vec<Vec2>,
+}
+
+impl EwaldTable2D {
+    pub fn new(box_size: f32) -> Self {
+        let spacing = box_size as f64 / 2.0 / RESOLUTION as f64;
+        let mut corrections = Vec::with_capacity((RESOLUTION + 1) * (RESOLUTION + 1));
+        for j in 0..=RESOLUTION {
+            for i in 0..=RESOLUTION {
+                let offset = [i as f64 * spacing, j as f64 * spacing];
+                let [x, y] = ewald_correction(offset, box_size as f64);
+                corrections.push(vec2(x as f32, y as f32));
+            }
+        }
+        Self {
+            box_size,
+            corrections,
+        }
+    }
+
+    /// The correction to the field of a unit source at `offset` from the target, which must
+    /// already be the offset to the source's nearest image.
+    pub fn correction(&self, offset: Vec2) -> Vec2 {
+        let scaled = offset.abs() / (self.box_size / 2.0) * RESOLUTION as f32;
+        let i = (scaled.x as usize).min(RESOLUTION - 1);
+        let j = (scaled.y as usize).min(RESOLUTION - 1);
+        let (u, v) = (scaled.x - i as f32, scaled.y - j as f32);
+
+        let at = |i: usize, j: usize| self.corrections[j * (RESOLUTION + 1) + i];
+        let correction = at(i, j) * (1.0 - u) * (1.0 - v)
+            + at(i + 1, j) * u * (1.0 - v)
+            + at(i, j + 1) * (1.0 - u) * v
+            + at(i + 1, j + 1) * u * v;
+
+        // The field of the images is odd in each component of the offset.
+        let sign = |component: f32| if component < 0.0 { -1.0 } else { 1.0 };
+        vec2(correction.x * sign(offset.x), correction.y * sign(offset.y))
+    }
+}
+
+/// The field of a unit source and all its periodic images at `offset` from the target, less that
+/// of the source itself.
+fn ewald_correction(offset: [f64; 2], box_size: f64) -> [f64; 2] {
+    // The splitting between real and reciprocal space, chosen so both sums converge quickly.
+    let alpha = 2.0 / box_size;
+    let mut field = [0.0, 0.0];
+
+    for nx in -REAL_SPACE_IMAGES..=REAL_SPACE_IMAGES {
+        for ny in -REAL_SPACE_IMAGES..=REAL_SPACE_IMAGES {
+            let image = [
+                offset[0] + nx as f64 * box_size,
+                offset[1] + ny as f64 * box_size,
+            ];
+            let distance = image[0].hypot(image[1]);
+            if distance == 0.0 {
+                continue;
+            }
+            let screened = erfc(alpha * distance)
+                + 2.0 * alpha * distance / PI.sqrt() * (-alpha * alpha * distance * distance).exp();
+            // The source itself is counted unscreened by the caller, so remove it here.
+            let unscreened = if nx == 0 && ny == 0 { 1.0 } else { 0.0 };
+            let strength = (screened - unscreened) / (distance * distance * distance);
+            field[0] += image[0] * strength;
+            field[1] += image[1] * strength;
+        }
+    }
+
+    let wave_number = 2.0 * PI / box_size;
+    let area = box_size * box_size;
+    for mx in -RECIPROCAL_SPACE_WAVES..=RECIPROCAL_SPACE_WAVES {
+        for my in -RECIPROCAL_SPACE_WAVES..=RECIPROCAL_SPACE_WAVES {
+            if mx == 0 && my == 0 {
+                continue;
+            }
+            let k = [mx as f64 * wave_number, my as f64 * wave_number];
+            let k_length = k[0].hypot(k[1]);
+            let strength = 2.0 * PI / area
+                * (k[0] * offset[0] + k[1] * offset[1]).sin()
+                * erfc(k_length / (2.0 * alpha))
+                / k_length;
+            field[0] += k[0] * strength;
+            field[1] += k[1] * strength;
+        }
+    }
+
+    field
+}
+
+/// The complementary error function, to within 1.2e-7 (Numerical Recipes' `erfcc`).
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let polynomial = -z * z - 1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807
+                            + t * (-1.13520398
+                                + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277))))))));
+    let result = t * polynomial.exp();
+    if x >= 0.0 {
+        result
+    } else {
+        2.0 - result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::point_mass::PointMass;
+    use crate::physics::space_2d::Space2D;
+
+    #[test]
+    fn test_field_vanishes_at_symmetry_points() {
+        let box_size = 100.0;
+        let periodic = PeriodicBox::<Space2D>::new(box_size);
+        let source = PointMass::<Space2D>::new(vec2(0.0, 0.0), 1.0);
+        // Halfway between a source and its images, their pulls cancel.
+        for offset in [vec2(0.5, 0.0), vec2(0.0, 0.5), vec2(0.5, 0.5)] {
+            let offset = offset * box_size;
+            let g = source.g_towards(offset, 1.0) + periodic.ewald_correction(offset);
+            assert!(g.length() < 1e-4 / (box_size * box_size), "{:?}", g);
+        }
+    }
+
+    /// The field at `offset` from the target of the images of a unit source in the square of
+    /// `shells` boxes around it, other than the source itself.
+    fn image_sum(offset: [f64; 2], box_size: f64, shells: i32) -> [f64; 2] {
+        let mut field = [0.0, 0.0];
+        for nx in -shells..=shells {
+            for ny in -shells..=shells {
+                if nx == 0 && ny == 0 {
+                    continue;
+                }
+                let image = [
+                    offset[0] + nx as f64 * box_size,
+                    offset[1] + ny as f64 * box_size,
+                ];
+                let distance = image[0].hypot(image[1]);
+                let inverse_cube = 1.0 / (distance * distance * distance);
+                field[0] += image[0] * inverse_cube;
+                field[1] += image[1] * inverse_cube;
+            }
+        }
+        field
+    }
+
+    #[test]
+    fn test_ewald_sum_matches_image_sum() {
+        let box_size = 100.0;
+        for offset in [[0.13, 0.31], [0.37, -0.22], [-0.41, 0.07], [0.02, 0.45]] {
+            let offset = offset.map(|component| component * box_size);
+            // The image sum's error falls as the inverse of the number of shells, so extrapolate
+            // to infinitely many.
+            let (coarse, fine) = (
+                image_sum(offset, box_size, 100),
+                image_sum(offset, box_size, 200),
+            );
+            let exact = [2.0 * fine[0] - coarse[0], 2.0 * fine[1] - coarse[1]];
+            let scale = exact[0].hypot(exact[1]);
+
+            let summed = ewald_correction(offset, box_size);
+            let error = (summed[0] - exact[0]).hypot(summed[1] - exact[1]);
+            assert!(error < 1e-4 * scale, "{offset:?}: {summed:?} vs {exact:?}");
+
+            let tabulated = PeriodicBox::<Space2D>::new(box_size as f32)
+                .ewald_correction(vec2(offset[0] as f32, offset[1] as f32));
+            let error = (tabulated.x as f64 - exact[0]).hypot(tabulated.y as f64 - exact[1]);
+            assert!(
+                error < 1e-3 * scale,
+                "{offset:?}: {tabulated:?} vs {exact:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_interpolation_between_table_nodes() {
+        let box_size = 100.0;
+        let table = EwaldTable2D::new(box_size);
+        let spacing = box_size / 2.0 / RESOLUTION as f32;
+        let (mut max_error, mut max_correction) = (0.0f64, 0.0f64);
+        // The interpolation is worst furthest from the nodes, at the centres of the table cells.
+        for j in 0..RESOLUTION {
+            for i in 0..RESOLUTION {
+                let offset = vec2(i as f32 + 0.5, j as f32 + 0.5) * spacing;
+                let exact = ewald_correction([offset.x as f64, offset.y as f64], box_size as f64);
+                let interpolated = table.correction(offset);
+                max_error = max_error.max(
+                    (interpolated.x as f64 - exact[0]).hypot(interpolated.y as f64 - exact[1]),
+                );
+                max_correction = max_correction.max(exact[0].hypot(exact[1]));
+            }
+        }
+        assert!(
+            max_error < 1e-3 * max_correction,
+            "{max_error} of {max_correction}"
+        );
+    }
+}
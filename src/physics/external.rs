@@ -0,0 +1,165 @@
+use nannou::prelude::*;
+
+use super::point_mass::PointMass;
+use super::space_2d::Space2D;
+
+/// The shape of an analytic external potential.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Potential {
+    PointMass {
+        mass: f32,
+    },
+    /// A Navarro-Frenk-White dark matter halo, whose density falls off as `r^-1` inside
+    /// `scale_radius` and `r^-3` outside.  `mass` is `4 * PI * rho_0 * scale_radius^3`, the mass
+    /// enclosed within about 5.3 scale radii.
+    Nfw {
+        mass: f32,
+        scale_radius: f32,
+    },
+    /// A logarithmic halo, with a flat rotation curve at `circular_velocity` outside
+    /// `core_radius`.
+    Logarithmic {
+        circular_velocity: f32,
+        core_radius: f32,
+    },
+    /// A Miyamoto-Nagai disk seen face on, so that only the sum of its scale length and scale
+    /// height matters in the plane of the disk.
+    MiyamotoNagai {
+        mass: f32,
+        scale_length: f32,
+        scale_height: f32,
+    },
+    /// A field with the same strength and direction everywhere.
+    Uniform {
+        field: Vec2,
+    },
+}
+
+/// An analytic potential centred on a fixed position, whose field is added to that of the
+/// particles.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct External {
+    pub position: Point2,
+    pub potential: Potential,
+}
+
+impl Potential {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Potential::PointMass { .. } => "point mass",
+            Potential::Nfw { .. } => "NFW halo",
+            Potential::Logarithmic { .. } => "logarithmic halo",
+            Potential::MiyamotoNagai { .. } => "Miyamoto-Nagai disk",
+            Potential::Uniform { .. } => "uniform field",
+        }
+    }
+
+    /// Each kind of potential, with parameters that suit the default particle distribution.
+    pub fn defaults() -> [Self; 5] {
+        [
+            Potential::PointMass { mass: 1e3 },
+            Potential::Nfw {
+                mass: 1e4,
+                scale_radius: 200.0,
+            },
+            Potential::Logarithmic {
+                circular_velocity: 50.0,
+                core_radius: 50.0,
+            },
+            Potential::MiyamotoNagai {
+                mass: 1e4,
+                scale_length: 150.0,
+                scale_height: 20.0,
+            },
+            Potential::Uniform {
+                field: vec2(0.0, -10.0),
+            },
+        ]
+    }
+}
+
+impl External {
+    pub fn new(position: Point2, potential: Potential) -> Self {
+        Self {
+            position,
+            potential,
+        }
+    }
+
+    pub fn g_at(&self, target: Point2, grav_const: f32) -> Vec2 {
+        let target_to_centre = self.position - target;
+        let r_squared = target_to_centre.length_squared();
+        let r = r_squared.sqrt();
+        match self.potential {
+            Potential::PointMass { mass } => {
+                PointMass::<Space2D>::new(self.position, mass).g_at(target, grav_const)
+            }
+            Potential::Nfw { mass, scale_radius } => {
+                if r == 0.0 {
+                    return Vec2::ZERO;
+                }
+                let x = r / scale_radius;
+                let enclosed_mass = mass * ((1.0 + x).ln() - x / (1.0 + x));
+                target_to_centre * (grav_const * enclosed_mass / (r_squared * r))
+            }
+            Potential::Logarithmic {
+                circular_velocity,
+                core_radius,
+            } => {
+                target_to_centre * (circular_velocity * circular_velocity)
+                    / (core_radius * core_radius + r_squared)
+            }
+            Potential::MiyamotoNagai {
+                mass,
+                scale_length,
+                scale_height,
+            } => {
+                let softening = scale_length + scale_height;
+                let denominator = (r_squared + softening * softening).powf(1.5);
+                target_to_centre * (grav_const * mass / denominator)
+            }
+            Potential::Uniform { field } => field,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn circular_velocity(external: &External, r: f32) -> f32 {
+        let g = external.g_at(external.position + vec2(r, 0.0), 1.0);
+        (g.length() * r).sqrt()
+    }
+
+    #[test]
+    fn test_limits() {
+        let centre = pt2(30.0, -40.0);
+        let logarithmic = External::new(
+            centre,
+            Potential::Logarithmic {
+                circular_velocity: 50.0,
+                core_radius: 10.0,
+            },
+        );
+        assert!((circular_velocity(&logarithmic, 1e3) - 50.0).abs() < 0.1);
+
+        // Far away, a disk looks like a point mass.
+        let point_mass = External::new(centre, Potential::PointMass { mass: 1e4 });
+        let disk = External::new(
+            centre,
+            Potential::MiyamotoNagai {
+                mass: 1e4,
+                scale_length: 5.0,
+                scale_height: 1.0,
+            },
+        );
+        let far = centre + vec2(0.0, 1e3);
+        let (disk_g, point_g) = (disk.g_at(far, 1.0), point_mass.g_at(far, 1.0));
+        assert!((disk_g - point_g).length() < 1e-3 * point_g.length());
+
+        // Halos pull towards their centres.
+        let nfw = External::new(centre, Potential::defaults()[1]);
+        assert!(nfw.g_at(centre + vec2(100.0, 0.0), 1.0).x < 0.0);
+    }
+}
@@ -0,0 +1,494 @@
+use std::ops::{AddAssign, Range};
+
+use nannou::geom::{vec2, Rect};
+
+use crate::physics::barnes_hut::Body;
+use crate::physics::point_mass::PointMass;
+use crate::physics::solver::GravitySolver;
+use crate::physics::space::{DivisibleSpace, TensorSpace};
+use crate::physics::space_2d::Space2D;
+
+pub type FastMultipole2D = FastMultipole<Space2D, 4>;
+
+/// A second-order local expansion of the field about a cell's centre: the field at the centre,
+/// its gradient and its second derivatives.
+#[derive(Debug, Clone, Copy, Default)]
+struct LocalExpansion<S: TensorSpace> {
+    g: S::Vector,
+    gradient: S::Tensor,
+    hessian: S::Tensor3,
+}
+
+impl<S: TensorSpace> LocalExpansion<S> {
+    fn evaluate(&self, offset: S::Vector) -> S::Vector {
+        let curvature = S::transform(S::contract(self.hessian, offset), offset) / S::TWO;
+        self.g + S::transform(self.gradient, offset) + curvature
+    }
+
+    /// The same expansion about a centre at `offset` from this one's.
+    fn shifted(&self, offset: S::Vector) -> Self {
+        Self {
+            g: self.evaluate(offset),
+            gradient: self.gradient + S::contract(self.hessian, offset),
+            hessian: self.hessian,
+        }
+    }
+}
+
+impl<S: TensorSpace> AddAssign for LocalExpansion<S> {
+    fn add_assign(&mut self, rhs: Self) {
+        self.g += rhs.g;
+        self.gradient += rhs.gradient;
+        self.hessian += rhs.hessian;
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Cell<S: TensorSpace> {
+    pivot: S::Vector,
+    width: S::Scalar,
+    total: PointMass<S>,
+    /// The second moment of the cell's mass about `centre`, which with the total makes up its
+    /// multipole expansion.
+    quadrupole: S::Tensor,
+    /// The centre of the cell's multipole and local expansions: its centre of mass, or its pivot
+    /// if it's massless.
+    centre: S::Vector,
+    /// The distance from `centre` to the furthest body in the cell.
+    radius: S::Scalar,
+    /// The cell's bodies, which are contiguous in tree order.
+    bodies: Range<usize>,
+    /// The cell's non-empty subdivisions, which are contiguous in the cell list.
+    children: Range<usize>,
+}
+
+impl<S: TensorSpace> Cell<S> {
+    fn new(pivot: S::Vector, width: S::Scalar, bodies: Range<usize>) -> Self {
+        Self {
+            pivot,
+            width,
+            total: PointMass::default(),
+            quadrupole: S::TENSOR_ZERO,
+            centre: pivot,
+            radius: S::SCALAR_ZERO,
+            bodies,
+            children: 0..0,
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+}
+
+/// The pairs of cells which the dual-tree traversal finds to interact.
+struct InteractionLists {
+    /// For each cell, the well-separated cells whose multipoles are translated into its local
+    /// expansion.
+    far: Vec<Vec<usize>>,
+    /// For each leaf, the leaves whose bodies act directly on its own.
+    near: Vec<Vec<usize>>,
+}
+
+/// A fast multipole method solver, which evaluates the field with a dual-tree traversal:
+/// well-separated pairs of cells interact through a multipole-to-local translation, with the
+/// resulting local expansions passed down the tree to the bodies.
+///
+/// Each cell's multipole carries its total mass and quadrupole moment about its centre of mass,
+/// and local expansions are second order, so the error falls as the cube of `theta`.  The
+/// translations, the downward pass and the leaves are evaluated in parallel.
+#[derive(Debug, Clone)]
+pub struct FastMultipole<S, const NUM_SUBDIVISIONS: usize>
+where
+    S: DivisibleSpace<NUM_SUBDIVISIONS> + TensorSpace,
+{
+    /// The bodies, sorted so that each cell's bodies are contiguous.
+    bodies: Vec<Body<S>>,
+    /// The bodies' positions and masses in the same order, for the leaf kernel.
+    positions: Vec<S::Vector>,
+    masses: Vec<S::Scalar>,
+    /// The cells in the tree, with the root first and every cell before its children.
+    cells: Vec<Cell<S>>,
+}
+
+impl FastMultipole2D {
+    pub(crate) fn get_bounding_boxes(&self) -> Vec<Rect> {
+        self.cells
+            .iter()
+            .filter(|cell| !cell.is_leaf())
+            .map(|cell| Rect::from_xy_wh(cell.pivot, vec2(cell.width, cell.width)))
+            .collect()
+    }
+}
+
+impl<S, const NUM_SUBDIVISIONS: usize> FastMultipole<S, NUM_SUBDIVISIONS>
+where
+    S: DivisibleSpace<NUM_SUBDIVISIONS> + TensorSpace,
+{
+    /// Builds the tree over a space of the given size centred on the origin.  Cells holding at
+    /// most `leaf_capacity` bodies are not subdivided.
+    pub fn new(
+        size: S::Scalar,
+        leaf_capacity: usize,
+        bodies: impl IntoIterator<Item = Body<S>>,
+    ) -> Self {
+        let bodies: Vec<Body<S>> = bodies
+            .into_iter()
+            .filter(|body| {
+                let in_bounds = S::max_abs_dimension(body.point_mass.position) < size;
+                if !in_bounds {
+                    warn!("PointMass out of bounds: {:?}", body.point_mass);
+                }
+                in_bounds
+            })
+            .collect();
+
+        let mut fmm = Self {
+            cells: vec![Cell::new(S::VECTOR_ZERO, size, 0..bodies.len())],
+            bodies,
+            positions: Vec::new(),
+            masses: Vec::new(),
+        };
+        fmm.subdivide(0, at_least!(leaf_capacity, 1));
+        fmm.positions = fmm
+            .bodies
+            .iter()
+            .map(|body| body.point_mass.position)
+            .collect();
+        fmm.masses = fmm.bodies.iter().map(|body| body.point_mass.mass).collect();
+        fmm.compute_multipoles();
+        fmm
+    }
+
+    fn subdivide(&mut self, cell: usize, leaf_capacity: usize) {
+        let Cell {
+            pivot,
+            width,
+            bodies,
+            ..
+        } = self.cells[cell].clone();
+        if bodies.len() <= leaf_capacity || width < S::EPSILON {
+            return;
+        }
+
+        let subdivision_of = |body: &Body<S>| S::subdivision_index(pivot, body.point_mass.position);
+        self.bodies[bodies.clone()].sort_unstable_by_key(subdivision_of);
+
+        let first_child = self.cells.len();
+        let mut start = bodies.start;
+        for i in 0..NUM_SUBDIVISIONS {
+            let count = self.bodies[start..bodies.end]
+                .iter()
+                .take_while(|body| subdivision_of(body) == i)
+                .count();
+            if count > 0 {
+                let (width, pivot) = S::subtree_width_pivot(i, width, pivot);
+                self.cells
+                    .push(Cell::new(pivot, width, start..start + count));
+            }
+            start += count;
+        }
+
+        let children = first_child..self.cells.len();
+        self.cells[cell].children = children.clone();
+        for child in children {
+            self.subdivide(child, leaf_capacity);
+        }
+    }
+
+    /// Computes each cell's total mass, expansion centre, quadrupole moment and radius, from the
+    /// leaves up.
+    fn compute_multipoles(&mut self) {
+        for i in (0..self.cells.len()).rev() {
+            let cell = &self.cells[i];
+            let mut total = PointMass::default();
+            if cell.is_leaf() {
+                for body in &self.bodies[cell.bodies.clone()] {
+                    total += body.point_mass;
+                }
+            } else {
+                for child in &self.cells[cell.children.clone()] {
+                    total += child.total;
+                }
+            }
+            let centre = if total.mass > S::SCALAR_ZERO {
+                total.position
+            } else {
+                cell.pivot
+            };
+
+            let mut quadrupole = S::TENSOR_ZERO;
+            if cell.is_leaf() {
+                for body in &self.bodies[cell.bodies.clone()] {
+                    quadrupole +=
+                        S::outer(body.point_mass.position - centre) * body.point_mass.mass;
+                }
+            } else {
+                // The parallel axis theorem moves each child's moment to the parent's centre.
+                for child in &self.cells[cell.children.clone()] {
+                    quadrupole +=
+                        child.quadrupole + S::outer(child.centre - centre) * child.total.mass;
+                }
+            }
+
+            let reach = |reach: S::Scalar, extent: S::Scalar| {
+                if extent > reach {
+                    extent
+                } else {
+                    reach
+                }
+            };
+            let radius = if cell.is_leaf() {
+                self.bodies[cell.bodies.clone()]
+                    .iter()
+                    .map(|body| S::magnitude(body.point_mass.position - centre))
+                    .fold(S::SCALAR_ZERO, reach)
+            } else {
+                self.cells[cell.children.clone()]
+                    .iter()
+                    .map(|child| S::magnitude(child.centre - centre) + child.radius)
+                    .fold(S::SCALAR_ZERO, reach)
+            };
+
+            let cell = &mut self.cells[i];
+            cell.total = total;
+            cell.quadrupole = quadrupole;
+            cell.centre = centre;
+            cell.radius = radius;
+        }
+    }
+
+    /// Finds the pairs of cells through which the `source` cell's bodies act on those of the `sink`
+    /// cell: through its multipole where the two are well separated, and directly between leaves
+    /// otherwise.
+    fn interact(&self, sink: usize, source: usize, theta: S::Scalar, lists: &mut InteractionLists) {
+        let (sink_cell, source_cell) = (&self.cells[sink], &self.cells[source]);
+        if source_cell.total.mass == S::SCALAR_ZERO {
+            return;
+        }
+
+        if sink == source {
+            if sink_cell.is_leaf() {
+                lists.near[sink].push(source);
+            } else {
+                for i in sink_cell.children.clone() {
+                    for j in sink_cell.children.clone() {
+                        self.interact(i, j, theta, lists);
+                    }
+                }
+            }
+            return;
+        }
+
+        let separation = S::magnitude(source_cell.centre - sink_cell.centre);
+        if sink_cell.radius + source_cell.radius < theta * separation {
+            lists.far[sink].push(source);
+            return;
+        }
+
+        let split_sink = match (sink_cell.is_leaf(), source_cell.is_leaf()) {
+            (true, true) => {
+                lists.near[sink].push(source);
+                return;
+            }
+            (true, false) => false,
+            (false, true) => true,
+            (false, false) => sink_cell.radius > source_cell.radius,
+        };
+        if split_sink {
+            for i in sink_cell.children.clone() {
+                self.interact(i, source, theta, lists);
+            }
+        } else {
+            for j in source_cell.children.clone() {
+                self.interact(sink, j, theta, lists);
+            }
+        }
+    }
+
+    /// The field of the `source` cell's multipole, as a local expansion about the `sink` cell's
+    /// centre.  The quadrupole's contributions to the field's derivatives are of higher order than
+    /// the expansion keeps, so only the field itself includes it.
+    fn translate(&self, sink: usize, source: usize, grav_const: S::Scalar) -> LocalExpansion<S> {
+        let (sink, source) = (&self.cells[sink], &self.cells[source]);
+        let offset = source.centre - sink.centre;
+        let quadrupole_g = S::contract_tensor(
+            S::inverse_square_hessian(offset, grav_const),
+            source.quadrupole,
+        ) / S::TWO;
+        LocalExpansion {
+            g: source.total.g_at(sink.centre, grav_const) + quadrupole_g,
+            gradient: source.total.g_gradient_at(sink.centre, grav_const),
+            hessian: S::inverse_square_hessian(offset, source.total.mass * grav_const),
+        }
+    }
+
+    /// The net g at each of the leaf's bodies, in tree order, from its local expansion and the
+    /// bodies of its near leaves.
+    fn evaluate_leaf(
+        &self,
+        leaf: usize,
+        local: &LocalExpansion<S>,
+        near: &[usize],
+        grav_const: S::Scalar,
+    ) -> Vec<S::Vector> {
+        let cell = &self.cells[leaf];
+        self.positions[cell.bodies.clone()]
+            .iter()
+            .map(|&position| {
+                let mut net_g = local.evaluate(position - cell.centre);
+                for &source in near {
+                    let bodies = self.cells[source].bodies.clone();
+                    net_g += S::inverse_square_sum(
+                        position,
+                        &self.positions[bodies.clone()],
+                        &self.masses[bodies],
+                    ) * grav_const;
+                }
+                net_g
+            })
+            .collect()
+    }
+}
+
+/// Maps `f` over `0..count`, in parallel if rayon is enabled.
+fn map_indices<T: Send>(count: usize, f: impl Fn(usize) -> T + Send + Sync) -> Vec<T> {
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+        (0..count).into_par_iter().map(f).collect()
+    }
+    #[cfg(not(feature = "rayon"))]
+    {
+        (0..count).map(f).collect()
+    }
+}
+
+impl<S, const NUM_SUBDIVISIONS: usize> GravitySolver<S> for FastMultipole<S, NUM_SUBDIVISIONS>
+where
+    S: DivisibleSpace<NUM_SUBDIVISIONS> + TensorSpace,
+{
+    fn estimate_net_g_all(
+        &self,
+        positions: &[S::Vector],
+        theta: S::Scalar,
+        grav_const: S::Scalar,
+    ) -> Vec<S::Vector> {
+        let mut lists = InteractionLists {
+            far: vec![Vec::new(); self.cells.len()],
+            near: vec![Vec::new(); self.cells.len()],
+        };
+        if !self.bodies.is_empty() {
+            self.interact(0, 0, theta, &mut lists);
+        }
+
+        let mut locals = map_indices(self.cells.len(), |sink| {
+            let mut local = LocalExpansion::default();
+            for &source in &lists.far[sink] {
+                local += self.translate(sink, source, grav_const);
+            }
+            local
+        });
+
+        // Pass the local expansions down a level at a time, so that each parent's is complete
+        // before it's shifted to its children.
+        let mut level = vec![0];
+        while !level.is_empty() {
+            let edges: Vec<(usize, usize)> = level
+                .iter()
+                .flat_map(|&parent| {
+                    self.cells[parent]
+                        .children
+                        .clone()
+                        .map(move |child| (parent, child))
+                })
+                .collect();
+            let shifted = map_indices(edges.len(), |i| {
+                let (parent, child) = edges[i];
+                locals[parent].shifted(self.cells[child].centre - self.cells[parent].centre)
+            });
+            for (&(_, child), shifted) in edges.iter().zip(shifted) {
+                locals[child] += shifted;
+            }
+            level = edges.into_iter().map(|(_, child)| child).collect();
+        }
+
+        let leaves: Vec<usize> = (0..self.cells.len())
+            .filter(|&i| self.cells[i].is_leaf())
+            .collect();
+        let leaf_net_gs = map_indices(leaves.len(), |i| {
+            let leaf = leaves[i];
+            self.evaluate_leaf(leaf, &locals[leaf], &lists.near[leaf], grav_const)
+        });
+
+        let mut net_gs = vec![S::VECTOR_ZERO; positions.len()];
+        for (leaf, leaf_net_gs) in leaves.into_iter().zip(leaf_net_gs) {
+            for (body, net_g) in self.bodies[self.cells[leaf].bodies.clone()]
+                .iter()
+                .zip(leaf_net_gs)
+            {
+                if let Some(slot) = net_gs.get_mut(body.index) {
+                    *slot = net_g;
+                }
+            }
+        }
+        net_gs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nannou::geom::{pt2, Point2};
+
+    use super::*;
+
+    /// Bodies spread evenly over a disk, in a sunflower pattern.
+    fn disk(n: usize) -> Vec<Body<Space2D>> {
+        (0..n)
+            .map(|i| {
+                let r = 300.0 * (i as f32 / n as f32).sqrt();
+                let angle = i as f32 * 2.4;
+                let position = pt2(r * angle.cos(), r * angle.sin());
+                Body::new(i, PointMass::new(position, 1.0 + (i % 3) as f32))
+            })
+            .collect()
+    }
+
+    fn direct_sum(bodies: &[Body<Space2D>]) -> Vec<Point2> {
+        bodies
+            .iter()
+            .map(|target| {
+                bodies.iter().fold(Point2::ZERO, |sum, source| {
+                    sum + source.point_mass.g_at(target.point_mass.position, 1.0)
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_fmm_matches_direct_sum() {
+        let bodies = disk(500);
+        let positions: Vec<Point2> = bodies.iter().map(|b| b.point_mass.position).collect();
+        let exact = direct_sum(&bodies);
+
+        let fmm = FastMultipole2D::new(2048.0, 4, bodies);
+        let rms_relative_error = |theta: f32| {
+            let estimated = fmm.estimate_net_g_all(&positions, theta, 1.0);
+            let error_squared: f32 = exact
+                .iter()
+                .zip(&estimated)
+                .map(|(e, g)| (*e - *g).length_squared() / e.length_squared())
+                .sum();
+            (error_squared / exact.len() as f32).sqrt()
+        };
+        let errors = [0.0, 0.1, 0.2].map(rms_relative_error);
+        for (error, tolerance) in errors.into_iter().zip([1e-5, 5e-4, 5e-3]) {
+            assert!(error <= tolerance, "rms relative errors {errors:?}");
+        }
+        // With quadrupoles and second-order local expansions, the error is at least third order.
+        assert!(
+            errors[2] >= 6.0 * errors[1],
+            "rms relative errors {errors:?}"
+        );
+    }
+}
@@ -0,0 +1,261 @@
+use std::fmt::Debug;
+use std::ops::AddAssign;
+
+use nannou::geom::Vec2;
+
+use crate::physics::point_mass::PointMass;
+use crate::physics::space::Space;
+use crate::physics::space_2d::Space2D;
+
+/// The pairwise interaction between bodies that a `GravityField` approximates.
+///
+/// Each body sources the field with a strength, which is its mass for gravity or its charge for
+/// electrostatics, and the field acts on the same strength, so that a body's acceleration is
+/// `field * strength / mass`.
+pub trait ForceLaw<S: Space>: Debug + Clone + Copy + Default + Send + Sync {
+    fn strength(&self, mass: S::Scalar, charge: S::Scalar) -> S::Scalar;
+
+    /// The field at a target due to a point source of the given strength at `target_to_source`.
+    fn field(
+        &self,
+        strength: S::Scalar,
+        target_to_source: S::Vector,
+        coupling: S::Scalar,
+    ) -> S::Vector;
+
+    /// The constant `k` of an inverse-square law, whose field is `k * strength / r^2` towards the
+    /// source, or `None` for other laws.  Only inverse-square laws can account for the periodic
+    /// images of their sources by Ewald summation.
+    fn inverse_square_coupling(&self, coupling: S::Scalar) -> Option<S::Scalar>;
+
+    /// Transforms the sum of the fields of all the sources at a target into the field the target
+    /// actually feels.  This is applied by the caller, once every source has been accounted for.
+    fn net_field(&self, field: S::Vector) -> S::Vector {
+        field
+    }
+}
+
+/// The moments of a group of sources, with those of positive and negative strength kept apart so
+/// that the group's field is still approximated well when they nearly cancel.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Moments<S: Space> {
+    positive: PointMass<S>,
+    /// Holds the magnitude of the negative strengths.
+    negative: PointMass<S>,
+}
+
+impl<S: Space> Moments<S> {
+    pub fn of(position: S::Vector, strength: S::Scalar) -> Self {
+        let mut moments = Self::default();
+        if strength > S::SCALAR_ZERO {
+            moments.positive = PointMass::new(position, strength);
+        } else if strength < S::SCALAR_ZERO {
+            moments.negative = PointMass::new(position, S::SCALAR_ZERO - strength);
+        }
+        moments
+    }
+
+    /// The total magnitude of the group's strengths, which bounds the strength of its field.
+    pub fn absolute_strength(&self) -> S::Scalar {
+        self.positive.mass + self.negative.mass
+    }
+
+    /// Point sources, with signed strengths in place of mass, which together stand in for the
+    /// group.
+    pub fn sources(&self) -> impl Iterator<Item = PointMass<S>> {
+        let negative = PointMass::new(self.negative.position, S::SCALAR_ZERO - self.negative.mass);
+        [self.positive, negative]
+            .into_iter()
+            .filter(|source| source.mass != S::SCALAR_ZERO)
+    }
+}
+
+impl<S: Space> AddAssign for Moments<S> {
+    fn add_assign(&mut self, rhs: Self) {
+        self.positive += rhs.positive;
+        self.negative += rhs.negative;
+    }
+}
+
+/// Attractive inverse-square gravity.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Newtonian;
+
+impl<S: Space> ForceLaw<S> for Newtonian {
+    fn strength(&self, mass: S::Scalar, _charge: S::Scalar) -> S::Scalar {
+        mass
+    }
+
+    fn field(
+        &self,
+        strength: S::Scalar,
+        target_to_source: S::Vector,
+        coupling: S::Scalar,
+    ) -> S::Vector {
+        PointMass::<S>::new(S::VECTOR_ZERO, strength).g_towards(target_to_source, coupling)
+    }
+
+    fn inverse_square_coupling(&self, coupling: S::Scalar) -> Option<S::Scalar> {
+        Some(coupling)
+    }
+}
+
+/// Electrostatics between signed charges, under which like charges repel.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Coulomb;
+
+impl<S: Space> ForceLaw<S> for Coulomb {
+    fn strength(&self, _mass: S::Scalar, charge: S::Scalar) -> S::Scalar {
+        charge
+    }
+
+    fn field(
+        &self,
+        strength: S::Scalar,
+        target_to_source: S::Vector,
+        coupling: S::Scalar,
+    ) -> S::Vector {
+        PointMass::<S>::new(S::VECTOR_ZERO, strength)
+            .g_towards(target_to_source, S::SCALAR_ZERO - coupling)
+    }
+
+    fn inverse_square_coupling(&self, coupling: S::Scalar) -> Option<S::Scalar> {
+        Some(S::SCALAR_ZERO - coupling)
+    }
+}
+
+/// Gravity screened beyond `range`, as if carried by a massive field.
+#[derive(Debug, Clone, Copy, Derivative, PartialEq)]
+#[derivative(Default)]
+pub struct Yukawa {
+    #[derivative(Default(value = "200.0"))]
+    pub range: f32,
+}
+
+impl ForceLaw<Space2D> for Yukawa {
+    fn strength(&self, mass: f32, _charge: f32) -> f32 {
+        mass
+    }
+
+    fn field(&self, strength: f32, target_to_source: Vec2, coupling: f32) -> Vec2 {
+        let distance = target_to_source.length();
+        let screening = (1.0 + distance / self.range) * (-distance / self.range).exp();
+        ForceLaw::<Space2D>::field(&Newtonian, strength, target_to_source, coupling) * screening
+    }
+
+    fn inverse_square_coupling(&self, _coupling: f32) -> Option<f32> {
+        None
+    }
+}
+
+/// Modified Newtonian dynamics: Newtonian gravity, boosted where it is weaker than
+/// `acceleration_scale` by the "simple" interpolating function.
+#[derive(Debug, Clone, Copy, Derivative, PartialEq)]
+#[derivative(Default)]
+pub struct Mond {
+    #[derivative(Default(value = "1.0"))]
+    pub acceleration_scale: f32,
+}
+
+impl ForceLaw<Space2D> for Mond {
+    fn strength(&self, mass: f32, _charge: f32) -> f32 {
+        mass
+    }
+
+    fn field(&self, strength: f32, target_to_source: Vec2, coupling: f32) -> Vec2 {
+        ForceLaw::<Space2D>::field(&Newtonian, strength, target_to_source, coupling)
+    }
+
+    fn inverse_square_coupling(&self, coupling: f32) -> Option<f32> {
+        Some(coupling)
+    }
+
+    fn net_field(&self, field: Vec2) -> Vec2 {
+        let y = field.length() / self.acceleration_scale;
+        if y <= 0.0 {
+            return field;
+        }
+        field * (0.5 + (0.25 + 1.0 / y).sqrt())
+    }
+}
+
+/// One of the force laws, chosen at runtime.
+#[derive(Debug, Clone, Copy, Derivative, PartialEq)]
+#[derivative(Default)]
+pub enum ForceLaw2D {
+    #[derivative(Default)]
+    Newtonian,
+    Coulomb,
+    Yukawa(Yukawa),
+    Mond(Mond),
+}
+
+impl ForceLaw<Space2D> for ForceLaw2D {
+    fn strength(&self, mass: f32, charge: f32) -> f32 {
+        match self {
+            ForceLaw2D::Newtonian => ForceLaw::<Space2D>::strength(&Newtonian, mass, charge),
+            ForceLaw2D::Coulomb => ForceLaw::<Space2D>::strength(&Coulomb, mass, charge),
+            ForceLaw2D::Yukawa(yukawa) => yukawa.strength(mass, charge),
+            ForceLaw2D::Mond(mond) => mond.strength(mass, charge),
+        }
+    }
+
+    fn field(&self, strength: f32, target_to_source: Vec2, coupling: f32) -> Vec2 {
+        match self {
+            ForceLaw2D::Newtonian => {
+                ForceLaw::<Space2D>::field(&Newtonian, strength, target_to_source, coupling)
+            }
+            ForceLaw2D::Coulomb => {
+                ForceLaw::<Space2D>::field(&Coulomb, strength, target_to_source, coupling)
+            }
+            ForceLaw2D::Yukawa(yukawa) => yukawa.field(strength, target_to_source, coupling),
+            ForceLaw2D::Mond(mond) => mond.field(strength, target_to_source, coupling),
+        }
+    }
+
+    fn inverse_square_coupling(&self, coupling: f32) -> Option<f32> {
+        match self {
+            ForceLaw2D::Newtonian => {
+                ForceLaw::<Space2D>::inverse_square_coupling(&Newtonian, coupling)
+            }
+            ForceLaw2D::Coulomb => ForceLaw::<Space2D>::inverse_square_coupling(&Coulomb, coupling),
+            ForceLaw2D::Yukawa(yukawa) => yukawa.inverse_square_coupling(coupling),
+            ForceLaw2D::Mond(mond) => mond.inverse_square_coupling(coupling),
+        }
+    }
+
+    fn net_field(&self, field: Vec2) -> Vec2 {
+        match self {
+            ForceLaw2D::Mond(mond) => mond.net_field(field),
+            _ => field,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nannou::geom::vec2;
+
+    use super::*;
+
+    #[test]
+    fn test_mond_limits() {
+        let mond = Mond::default();
+        // Strong fields are Newtonian...
+        let strong = vec2(1e4, 0.0);
+        assert!((mond.net_field(strong) - strong).length() < 1e-3 * strong.length());
+        // ...while weak ones tend to the geometric mean of the Newtonian field and the scale.
+        let weak = vec2(0.0, 1e-4);
+        let expected = (weak.length() * mond.acceleration_scale).sqrt();
+        assert!((mond.net_field(weak).length() - expected).abs() < 1e-2 * expected);
+    }
+
+    #[test]
+    fn test_opposite_charges_attract() {
+        let offset = vec2(10.0, 0.0);
+        let field = |law: ForceLaw2D, strength| law.field(strength, offset, 1.0);
+        assert!(field(ForceLaw2D::Coulomb, -1.0).x > 0.0);
+        assert!(field(ForceLaw2D::Coulomb, 1.0).x < 0.0);
+        assert!(field(ForceLaw2D::Newtonian, 1.0).x > 0.0);
+    }
+}
@@ -0,0 +1,177 @@
+use std::f32::consts::PI;
+
+use nannou::prelude::*;
+
+use super::particle::Particle;
+
+/// How a drag force depends on a particle's speed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Derivative)]
+#[derivative(Default)]
+pub enum DragMode {
+    #[derivative(Default)]
+    None,
+    /// Stokes drag, proportional to velocity.
+    Linear,
+    /// Turbulent drag, proportional to the square of the speed.
+    Quadratic,
+}
+
+/// A drag on every particle from a medium at rest.
+#[derive(Debug, Clone, Copy, PartialEq, Derivative)]
+#[derivative(Default)]
+pub struct Drag {
+    pub mode: DragMode,
+    #[derivative(Default(value = "0.1"))]
+    pub coefficient: f32,
+}
+
+impl Drag {
+    pub fn acceleration(&self, velocity: Vec2) -> Vec2 {
+        match self.mode {
+            DragMode::None => Vec2::ZERO,
+            DragMode::Linear => -velocity * self.coefficient,
+            DragMode::Quadratic => -velocity * (self.coefficient * velocity.length()),
+        }
+    }
+}
+
+/// Chandrasekhar dynamical friction, which slows massive bodies moving through a sea of lighter
+/// ones by the wake they raise.
+#[derive(Debug, Clone, Copy, PartialEq, Derivative)]
+#[derivative(Default)]
+pub struct DynamicalFriction {
+    pub enabled: bool,
+    /// Only bodies at least this massive feel friction.
+    #[derivative(Default(value = "100.0"))]
+    pub min_mass: f32,
+    /// The number of nearest neighbours from which the local density and velocity distribution
+    /// are estimated.
+    #[derivative(Default(value = "32"))]
+    pub neighbours: usize,
+    /// The logarithm of the ratio of the largest and smallest impact parameters that contribute.
+    #[derivative(Default(value = "3.0"))]
+    pub coulomb_logarithm: f32,
+}
+
+impl DynamicalFriction {
+    pub(super) fn applies_to(&self, mass: f32) -> bool {
+        self.enabled && mass >= self.min_mass
+    }
+
+    /// The friction on `body` from its neighbours, assuming they have a Maxwellian velocity
+    /// distribution about their mean velocity.  Chandrasekhar's formula is for the inverse-square
+    /// force the particles feel, so it needs a volume density: the neighbours' mass per unit volume
+    /// of the smallest sphere about the body containing them.
+    pub(super) fn acceleration(
+        &self,
+        body: &Particle,
+        neighbours: &[Particle],
+        grav_const: f32,
+    ) -> Vec2 {
+        let mass: f32 = neighbours.iter().map(|other| other.mass).sum();
+        if mass <= 0.0 {
+            return Vec2::ZERO;
+        }
+        let radius = neighbours
+            .iter()
+            .map(|other| (other.position - body.position).length())
+            .fold(0.0f32, f32::max);
+        if radius <= 0.0 {
+            return Vec2::ZERO;
+        }
+        let density = mass / (4.0 / 3.0 * PI * radius.powi(3));
+        let mean_velocity = neighbours
+            .iter()
+            .fold(Vec2::ZERO, |sum, other| sum + other.velocity * other.mass)
+            / mass;
+        // The one-dimensional dispersion, from the mean of the squares over both dimensions.
+        let dispersion = (neighbours
+            .iter()
+            .map(|other| other.mass * (other.velocity - mean_velocity).length_squared())
+            .sum::<f32>()
+            / (2.0 * mass))
+            .sqrt();
+
+        let velocity = body.velocity - mean_velocity;
+        let speed = velocity.length();
+        if speed == 0.0 {
+            return Vec2::ZERO;
+        }
+        let slower_fraction = if dispersion > 0.0 {
+            let x = speed / (2.0f32.sqrt() * dispersion);
+            erf(x) - 2.0 * x / PI.sqrt() * (-x * x).exp()
+        } else {
+            1.0
+        };
+        let strength = 4.0 * PI * grav_const * grav_const * body.mass * density;
+        -velocity * (strength * self.coulomb_logarithm * slower_fraction / speed.powi(3))
+    }
+}
+
+/// The error function, to within 1.5e-7 (Abramowitz and Stegun 7.1.26).
+fn erf(x: f32) -> f32 {
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let polynomial = t
+        * (0.254_829_6
+            + t * (-0.284_496_7 + t * (1.421_413_7 + t * (-1.453_152_1 + t * 1.061_405_4))));
+    (1.0 - polynomial * (-x * x).exp()).copysign(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_erf() {
+        assert!(erf(0.0).abs() < 1e-6);
+        assert!((erf(0.5) - 0.520_499_9).abs() < 1e-6);
+        assert!((erf(-2.0) + 0.995_322_3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_friction_through_cold_medium() {
+        let friction = DynamicalFriction {
+            enabled: true,
+            ..DynamicalFriction::default()
+        };
+        let mut body = Particle::new(pt2(0.0, 0.0));
+        body.velocity = vec2(0.0, 10.0);
+        let neighbours: Vec<Particle> = (0..8)
+            .map(|i| Particle::new(pt2(10.0, 0.0).rotate(i as f32 * PI / 4.0)))
+            .collect();
+
+        // Through a medium at rest, all of it is slower than the body.
+        let acceleration = friction.acceleration(&body, &neighbours, 1.0);
+        let density = 8.0 * 1000.0 / (4.0 / 3.0 * PI * 1000.0);
+        let expected = 4.0 * PI * 1000.0 * density * friction.coulomb_logarithm / 100.0;
+        assert!(acceleration.x.abs() < 1e-3);
+        assert!((acceleration.y + expected).abs() < 1e-3 * expected);
+    }
+
+    #[test]
+    fn test_friction_is_independent_of_units() {
+        let friction = DynamicalFriction {
+            enabled: true,
+            ..DynamicalFriction::default()
+        };
+        let acceleration = |length_unit: f32| {
+            // Lengths and speeds in units `length_unit` times smaller, with G to match.
+            let mut body = Particle::new(pt2(0.0, 0.0));
+            body.velocity = vec2(3.0, 4.0) * length_unit;
+            let neighbours: Vec<Particle> = (0..16)
+                .map(|i| Particle {
+                    velocity: vec2(1.0, 0.0).rotate(i as f32) * length_unit,
+                    ..Particle::new(pt2(5.0 + i as f32, 0.0).rotate(i as f32) * length_unit)
+                })
+                .collect();
+            friction.acceleration(&body, &neighbours, length_unit.powi(3))
+        };
+
+        let (base, scaled) = (acceleration(1.0), acceleration(10.0));
+        assert!(base.length() > 0.0);
+        assert!(
+            (scaled - base * 10.0).length() < 1e-3 * scaled.length(),
+            "{scaled:?} vs {base:?}"
+        );
+    }
+}
@@ -0,0 +1,14 @@
+use std::collections::BTreeMap;
+
+use nannou::prelude::*;
+
+/// Descriptive data attached to a particle, which doesn't affect the physics.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    pub name: String,
+    pub group: String,
+    /// Overrides the particle's colour when it's drawn.
+    pub colour: Option<Srgb<u8>>,
+    /// Any further labels, by key.
+    pub labels: BTreeMap<String, String>,
+}
@@ -1,8 +1,44 @@
+pub use barnes_hut::{ForceStrategy, OpeningCriterion};
+pub use bond::Spring;
+pub use boundary::BoundaryMode;
+pub use brush::{Brush, BrushShape};
+pub use collision::CollisionMode;
+pub use cosmology::Cosmology;
+pub use external::{External, Potential};
+pub use force_law::{ForceLaw2D, Mond, Yukawa};
+pub use friction::{Drag, DragMode, DynamicalFriction};
+pub use metadata::Metadata;
+pub use particle::ParticleId;
+pub use post_newtonian::PostNewtonian;
+pub use region::Region;
+pub use snapshot::Snapshot;
+pub use solver::Solver;
+pub use units::{UnitSystem, Units};
 pub use universe::Universe;
 
 mod barnes_hut;
+#[doc(hidden)]
+pub mod benchmarks;
+mod bond;
+mod boundary;
+mod brush;
+mod collision;
+mod cosmology;
+mod ewald;
+mod external;
+mod fmm;
+mod force_law;
+mod friction;
+mod metadata;
 mod particle;
+mod particles;
 mod point_mass;
+mod post_newtonian;
+mod region;
+mod snapshot;
+mod solver;
 mod space;
 mod space_2d;
+mod sph;
+mod units;
 mod universe;
@@ -5,21 +5,98 @@ use rand_distr::{Normal, Uniform};
 
 use ParticleType::*;
 
+use crate::drawing::alpha;
 use crate::view_state::ViewState;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum ParticleType {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum ParticleType {
     Default,
     Placed,
+    Gas(GasState),
+    /// Accretes any lighter particle that comes within `capture_radius`.
+    BlackHole {
+        capture_radius: f32,
+    },
+}
+
+/// The thermodynamic state of a gas particle, found by smoothed-particle hydrodynamics.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct GasState {
+    pub density: f32,
+    pub pressure: f32,
+    /// The internal energy per unit mass.
+    pub internal_energy: f32,
+    /// The rate of change of `internal_energy` due to compression and viscous heating.
+    pub energy_rate: f32,
+}
+
+impl ParticleType {
+    /// The capture radius of a black hole, or `None` for other particles.
+    pub fn capture_radius(&self) -> Option<f32> {
+        match self {
+            BlackHole { capture_radius } => Some(*capture_radius),
+            _ => None,
+        }
+    }
+
+    pub fn gas(&self) -> Option<&GasState> {
+        match self {
+            Gas(gas) => Some(gas),
+            _ => None,
+        }
+    }
+
+    pub fn gas_mut(&mut self) -> Option<&mut GasState> {
+        match self {
+            Gas(gas) => Some(gas),
+            _ => None,
+        }
+    }
+}
+
+impl GasState {
+    /// The gas state of a particle with the given internal energy per unit mass, whose density
+    /// and pressure are yet to be found.
+    pub fn new(internal_energy: f32) -> Self {
+        Self {
+            internal_energy,
+            ..Self::default()
+        }
+    }
+}
+
+/// Identifies a particle for as long as it exists, however the others are added, removed or
+/// reordered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct ParticleId(pub(super) u64);
+
+#[cfg(test)]
+impl ParticleId {
+    /// An id for tests which need one without a universe to issue it.
+    pub(crate) fn for_test(id: u64) -> Self {
+        Self(id)
+    }
+}
+
+impl std::fmt::Display for ParticleId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub(super) struct Particle {
-    tag: ParticleType,
+    pub(super) tag: ParticleType,
+    /// Assigned when the particle is added to a universe.
+    pub id: ParticleId,
     pub mass: f32,
     pub position: Point2,
     pub velocity: Vec2,
-    radius: f32,
+    /// The acceleration applied at the last update.
+    pub acceleration: Vec2,
+    pub radius: f32,
+    /// The signed charge felt by the Coulomb force law.
+    pub charge: f32,
 }
 
 impl Particle {
@@ -27,18 +104,12 @@ impl Particle {
         Self {
             position,
             velocity: vec2(0.0, 0.0),
+            acceleration: Vec2::ZERO,
+            charge: 0.0,
             mass: 1000.0,
             radius: 5.0,
             tag: Placed,
-        }
-    }
-    pub fn new_moving(position: Point2) -> Self {
-        Self {
-            position,
-            velocity: vec2(100.0, 0.0),
-            mass: 1000.0,
-            radius: 5.0,
-            tag: Placed,
+            id: ParticleId::default(),
         }
     }
     pub fn new_uniform() -> Self {
@@ -54,9 +125,12 @@ impl Particle {
         Self {
             position: normal_uniform_pt2(),
             velocity: vec2(0.0, 0.0),
+            acceleration: Vec2::ZERO,
+            charge: 0.0,
             mass: size * size * size,
             radius: size,
             tag: Default,
+            id: ParticleId::default(),
         }
     }
     pub fn new_random() -> Self {
@@ -77,22 +151,91 @@ impl Particle {
         Self {
             position,
             velocity,
+            acceleration: Vec2::ZERO,
+            charge: 0.0,
             mass: size * size * size,
             radius: size,
             tag: Default,
+            id: ParticleId::default(),
+        }
+    }
+
+    pub fn new_gas(position: Point2, mass: f32, internal_energy: f32) -> Self {
+        Self {
+            position,
+            velocity: vec2(0.0, 0.0),
+            acceleration: Vec2::ZERO,
+            charge: 0.0,
+            mass,
+            radius: mass.cbrt(),
+            tag: Gas(GasState::new(internal_energy)),
+            id: ParticleId::default(),
         }
     }
 
-    pub fn update(&mut self, dt: f32, acceleration: Vec2) {
-        self.velocity += acceleration * dt;
-        self.position += self.velocity * dt;
+    /// A gas particle in a random position and circular orbit, like `new_random`.
+    pub fn new_random_gas() -> Self {
+        let Self {
+            position, velocity, ..
+        } = Self::new_random();
+        Self {
+            velocity,
+            ..Self::new_gas(position, 2.0, 100.0)
+        }
     }
-    pub fn draw(&self, draw: &Draw, view_state: &ViewState, gradient: &Gradient<LinSrgba>) {
-        let color = match (self.tag, view_state.is_inspecting(self.position)) {
-            // (Placed, _) => alpha(TURQUOISE, 0.5),
-            // (_, true) => alpha(YELLOW, 0.2),
-            _ => self.get_color_from_velocity(gradient),
-        };
+
+    pub fn new_black_hole(
+        position: Point2,
+        velocity: Vec2,
+        mass: f32,
+        capture_radius: f32,
+    ) -> Self {
+        Self {
+            position,
+            velocity,
+            acceleration: Vec2::ZERO,
+            charge: 0.0,
+            mass,
+            radius: capture_radius,
+            tag: BlackHole { capture_radius },
+            id: ParticleId::default(),
+        }
+    }
+
+    /// A particle with the given charge in a random position and circular orbit, like
+    /// `new_random`.
+    pub fn new_random_charged(charge: f32) -> Self {
+        Self {
+            charge,
+            ..Self::new_random()
+        }
+    }
+
+    /// The capture radius of a black hole, or `None` for other particles.
+    pub fn capture_radius(&self) -> Option<f32> {
+        self.tag.capture_radius()
+    }
+
+    pub fn gas(&self) -> Option<&GasState> {
+        self.tag.gas()
+    }
+    /// Draws the particle in `colour` if it's given, or else in one depending on its type.
+    pub fn draw(
+        &self,
+        draw: &Draw,
+        view_state: &ViewState,
+        gradient: &Gradient<LinSrgba>,
+        colour: Option<LinSrgba>,
+    ) {
+        let color = colour.unwrap_or_else(|| {
+            match (self.tag, view_state.is_inspecting(self.position)) {
+                // (Placed, _) => alpha(TURQUOISE, 0.5),
+                // (_, true) => alpha(YELLOW, 0.2),
+                (Gas(_), _) => alpha(LIGHTBLUE, 0.3),
+                (BlackHole { .. }, _) => alpha(ORANGE, 0.9),
+                _ => self.get_color_from_velocity(gradient),
+            }
+        });
         let diameter = self.radius * 2.0;
         if diameter > view_state.min_universe_feature_size() {
             draw.ellipse()
@@ -0,0 +1,185 @@
+use nannou::prelude::*;
+
+use super::particle::{GasState, Particle, ParticleId, ParticleType};
+use super::point_mass::PointMass;
+use super::space_2d::Space2D;
+
+/// The particles of a universe, stored as a struct of arrays so that the loops over every particle
+/// each step only touch the fields they need, in contiguous memory.
+///
+/// Rows are read and written whole as `Particle`s where that's convenient, away from the hot loops.
+#[derive(Debug, Clone, Default)]
+pub(super) struct Particles {
+    tags: Vec<ParticleType>,
+    ids: Vec<ParticleId>,
+    masses: Vec<f32>,
+    positions: Vec<Point2>,
+    velocities: Vec<Vec2>,
+    /// The acceleration applied at the last step.
+    accelerations: Vec<Vec2>,
+    radii: Vec<f32>,
+    charges: Vec<f32>,
+}
+
+impl Particles {
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn push(&mut self, particle: Particle) {
+        self.tags.push(particle.tag);
+        self.ids.push(particle.id);
+        self.masses.push(particle.mass);
+        self.positions.push(particle.position);
+        self.velocities.push(particle.velocity);
+        self.accelerations.push(particle.acceleration);
+        self.radii.push(particle.radius);
+        self.charges.push(particle.charge);
+    }
+
+    pub fn get(&self, index: usize) -> Particle {
+        Particle {
+            tag: self.tags[index],
+            id: self.ids[index],
+            mass: self.masses[index],
+            position: self.positions[index],
+            velocity: self.velocities[index],
+            acceleration: self.accelerations[index],
+            radius: self.radii[index],
+            charge: self.charges[index],
+        }
+    }
+
+    pub fn set(&mut self, index: usize, particle: Particle) {
+        self.tags[index] = particle.tag;
+        self.ids[index] = particle.id;
+        self.masses[index] = particle.mass;
+        self.positions[index] = particle.position;
+        self.velocities[index] = particle.velocity;
+        self.accelerations[index] = particle.acceleration;
+        self.radii[index] = particle.radius;
+        self.charges[index] = particle.charge;
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Particle> + '_ {
+        (0..self.len()).map(|index| self.get(index))
+    }
+
+    /// Removes the marked particles, keeping the rest in order.
+    pub fn remove_marked(&mut self, removed: &[bool]) {
+        fn retain<T>(values: &mut Vec<T>, removed: &[bool]) {
+            let mut removed = removed.iter();
+            values.retain(|_| !removed.next().unwrap());
+        }
+        retain(&mut self.tags, removed);
+        retain(&mut self.ids, removed);
+        retain(&mut self.masses, removed);
+        retain(&mut self.positions, removed);
+        retain(&mut self.velocities, removed);
+        retain(&mut self.accelerations, removed);
+        retain(&mut self.radii, removed);
+        retain(&mut self.charges, removed);
+    }
+
+    pub fn ids(&self) -> &[ParticleId] {
+        &self.ids
+    }
+
+    pub fn masses(&self) -> &[f32] {
+        &self.masses
+    }
+
+    pub fn positions(&self) -> &[Point2] {
+        &self.positions
+    }
+
+    pub fn positions_mut(&mut self) -> &mut [Point2] {
+        &mut self.positions
+    }
+
+    pub fn velocities(&self) -> &[Vec2] {
+        &self.velocities
+    }
+
+    /// The positions and velocities, for changing both at once.
+    pub fn motion_mut(&mut self) -> (&mut [Point2], &mut [Vec2]) {
+        (&mut self.positions, &mut self.velocities)
+    }
+
+    pub fn accelerations(&self) -> &[Vec2] {
+        &self.accelerations
+    }
+
+    pub fn radii(&self) -> &[f32] {
+        &self.radii
+    }
+
+    pub fn charges(&self) -> &[f32] {
+        &self.charges
+    }
+
+    pub fn capture_radius(&self, index: usize) -> Option<f32> {
+        self.tags[index].capture_radius()
+    }
+
+    pub fn gas(&self, index: usize) -> Option<&GasState> {
+        self.tags[index].gas()
+    }
+
+    pub fn gas_mut(&mut self, index: usize) -> Option<&mut GasState> {
+        self.tags[index].gas_mut()
+    }
+
+    pub fn point_mass(&self, index: usize) -> PointMass<Space2D> {
+        PointMass::new(self.positions[index], self.masses[index])
+    }
+
+    /// Advances every particle by `dt` under the given accelerations, with the semi-implicit Euler
+    /// method, and integrates the internal energy of the gas particles.
+    pub fn integrate(&mut self, dt: f32, accelerations: &[Vec2]) {
+        let motion = self.positions.iter_mut().zip(&mut self.velocities);
+        for ((position, velocity), acceleration) in motion.zip(accelerations) {
+            *velocity += *acceleration * dt;
+            *position += *velocity * dt;
+        }
+        for gas in self.tags.iter_mut().filter_map(ParticleType::gas_mut) {
+            gas.internal_energy = (gas.internal_energy + gas.energy_rate * dt).max(0.0);
+        }
+        self.accelerations.copy_from_slice(accelerations);
+    }
+}
+
+impl FromIterator<Particle> for Particles {
+    fn from_iter<I: IntoIterator<Item = Particle>>(iter: I) -> Self {
+        let mut particles = Self::default();
+        for particle in iter {
+            particles.push(particle);
+        }
+        particles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rows_round_trip() {
+        let mut particles: Particles = (0..4)
+            .map(|i| Particle::new_gas(pt2(i as f32, 0.0), 1.0 + i as f32, 1.0))
+            .collect();
+        let mut particle = particles.get(2);
+        particle.velocity = vec2(1.0, 2.0);
+        particles.set(2, particle);
+        assert_eq!(particles.get(2), particle);
+
+        particles.remove_marked(&[true, false, false, true]);
+        assert_eq!(particles.len(), 2);
+        assert_eq!(particles.get(1), particle);
+        assert_eq!(particles.masses(), &[2.0, 3.0]);
+    }
+}
@@ -1,6 +1,6 @@
 use std::ops::AddAssign;
 
-use crate::physics::space::Space;
+use crate::physics::space::{Space, TensorSpace};
 
 #[derive(Debug, Clone, Copy, Default)]
 pub struct PointMass<S: Space> {
@@ -14,7 +14,11 @@ impl<S: Space> PointMass<S> {
     }
 
     pub fn g_at(&self, target: S::Vector, grav_constant: S::Scalar) -> S::Vector {
-        let target_to_self: S::Vector = self.position - target;
+        self.g_towards(self.position - target, grav_constant)
+    }
+
+    /// The field at a target from which this point mass lies at `target_to_self`.
+    pub fn g_towards(&self, target_to_self: S::Vector, grav_constant: S::Scalar) -> S::Vector {
         let distance_squared: S::Scalar = S::magnitude_squared(target_to_self);
         if distance_squared <= S::MIN_GRAVITY_DISTANCE_SQUARED {
             return S::VECTOR_ZERO;
@@ -24,6 +28,17 @@ impl<S: Space> PointMass<S> {
     }
 }
 
+impl<S: TensorSpace> PointMass<S> {
+    /// The gradient of `g_at` with respect to the target position.
+    pub fn g_gradient_at(&self, target: S::Vector, grav_constant: S::Scalar) -> S::Tensor {
+        let target_to_self: S::Vector = self.position - target;
+        if S::magnitude_squared(target_to_self) <= S::MIN_GRAVITY_DISTANCE_SQUARED {
+            return S::TENSOR_ZERO;
+        }
+        S::inverse_square_gradient(target_to_self, self.mass * grav_constant)
+    }
+}
+
 impl<S: Space> AddAssign for PointMass<S> {
     fn add_assign(&mut self, rhs: Self) {
         let total_mass: S::Scalar = self.mass + rhs.mass;
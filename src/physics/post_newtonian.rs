@@ -0,0 +1,139 @@
+use nannou::prelude::*;
+
+/// Post-Newtonian corrections to the gravity of black holes, which make orbits close to them
+/// precess and, with radiation reaction, decay.
+///
+/// The corrections are those to the relative acceleration of two bodies in harmonic coordinates,
+/// shared between the pair in proportion to the other's mass.  Only black holes apply them, so
+/// between a black hole and a light particle the black hole's share is neglected.
+#[derive(Debug, Clone, Copy, PartialEq, Derivative)]
+#[derivative(Default)]
+pub struct PostNewtonian {
+    pub enabled: bool,
+    /// Adds the 2.5PN term, through which orbits lose energy to gravitational waves.
+    pub radiation_reaction: bool,
+    /// The speed of light in simulation units.
+    #[derivative(Default(value = "1000.0"))]
+    pub speed_of_light: f32,
+}
+
+impl PostNewtonian {
+    /// The correction to the acceleration of a target of `target_mass` due to a source of
+    /// `source_mass`, where `offset` and `relative_velocity` are the target's relative to the
+    /// source.
+    pub(super) fn acceleration(
+        &self,
+        offset: Vec2,
+        relative_velocity: Vec2,
+        target_mass: f32,
+        source_mass: f32,
+        grav_const: f32,
+    ) -> Vec2 {
+        let total_mass = target_mass + source_mass;
+        let r = offset.length();
+        if !self.enabled || r == 0.0 || total_mass <= 0.0 {
+            return Vec2::ZERO;
+        }
+        let n = offset / r;
+        let v = relative_velocity;
+        let (v_squared, r_dot) = (v.length_squared(), n.dot(v));
+        let eta = target_mass * source_mass / (total_mass * total_mass);
+        let gm = grav_const * total_mass;
+        let c_squared = self.speed_of_light * self.speed_of_light;
+
+        let first_order = -(n
+            * ((1.0 + 3.0 * eta) * v_squared
+                - 2.0 * (2.0 + eta) * gm / r
+                - 1.5 * eta * r_dot * r_dot)
+            - v * (2.0 * (2.0 - eta) * r_dot))
+            * (gm / (r * r * c_squared));
+
+        let radiation_reaction = if self.radiation_reaction {
+            (n * ((3.0 * v_squared + 17.0 / 3.0 * gm / r) * r_dot) - v * (v_squared + 3.0 * gm / r))
+                * (1.6 * eta * gm * gm / (r.powi(3) * c_squared * c_squared * self.speed_of_light))
+        } else {
+            Vec2::ZERO
+        };
+
+        (first_order + radiation_reaction) * (source_mass / total_mass)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    const ORBITS: usize = 5;
+    const STEPS_PER_ORBIT: usize = 20_000;
+
+    /// The angle of the last pericentre of a test particle orbiting a unit mass with `G = 1` for
+    /// `ORBITS` and a half, starting at pericentre on the x axis.
+    fn pericentre_angle(post_newtonian: &PostNewtonian, semi_major_axis: f32, e: f32) -> f32 {
+        let pericentre = semi_major_axis * (1.0 - e);
+        let mut position = vec2(pericentre, 0.0);
+        let mut velocity = vec2(0.0, ((1.0 + e) / pericentre).sqrt());
+        let period = 2.0 * PI * semi_major_axis.powf(1.5);
+        let dt = period / STEPS_PER_ORBIT as f32;
+
+        let acceleration = |position: Vec2, velocity: Vec2| {
+            let r = position.length();
+            -position / (r * r * r) + post_newtonian.acceleration(position, velocity, 0.0, 1.0, 1.0)
+        };
+        // Leapfrog, with the velocity dependent term evaluated at the half step.
+        let (mut previous, mut angle) = (pericentre, 0.0);
+        let mut approaching = false;
+        for _ in 0..STEPS_PER_ORBIT * (2 * ORBITS + 1) / 2 {
+            let half = velocity + acceleration(position, velocity) * (dt / 2.0);
+            position += half * dt;
+            velocity = half + acceleration(position, half) * (dt / 2.0);
+            let r = position.length();
+            if approaching && r > previous {
+                angle = position.y.atan2(position.x);
+            }
+            approaching = r < previous;
+            previous = r;
+        }
+        angle
+    }
+
+    #[test]
+    fn test_perihelion_precession() {
+        let (semi_major_axis, e) = (1.0, 0.5);
+        let post_newtonian = PostNewtonian {
+            enabled: true,
+            speed_of_light: 30.0,
+            ..PostNewtonian::default()
+        };
+        let newtonian = PostNewtonian::default();
+
+        let precession = pericentre_angle(&post_newtonian, semi_major_axis, e)
+            - pericentre_angle(&newtonian, semi_major_axis, e);
+        let expected = ORBITS as f32 * 6.0 * PI
+            / (post_newtonian.speed_of_light.powi(2) * semi_major_axis * (1.0 - e * e));
+        assert!(
+            (precession - expected).abs() < 0.05 * expected,
+            "{} vs {}",
+            precession,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_radiation_reaction_removes_energy() {
+        let post_newtonian = PostNewtonian {
+            enabled: true,
+            radiation_reaction: true,
+            speed_of_light: 30.0,
+        };
+        let (offset, velocity) = (vec2(1.0, 0.0), vec2(0.0, 1.0));
+        let with = post_newtonian.acceleration(offset, velocity, 1.0, 1.0, 1.0);
+        let without = PostNewtonian {
+            radiation_reaction: false,
+            ..post_newtonian
+        }
+        .acceleration(offset, velocity, 1.0, 1.0, 1.0);
+        assert!((with - without).dot(velocity) < 0.0);
+    }
+}
@@ -0,0 +1,17 @@
+use nannou::prelude::*;
+
+/// An area of the universe, for picking out the particles to edit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Region {
+    Rect(Rect),
+    Circle { centre: Point2, radius: f32 },
+}
+
+impl Region {
+    pub fn contains(&self, point: Point2) -> bool {
+        match self {
+            Region::Rect(rect) => rect.contains(point),
+            Region::Circle { centre, radius } => (point - *centre).length() <= *radius,
+        }
+    }
+}
@@ -0,0 +1,19 @@
+use super::bond::Bond;
+use super::metadata::Metadata;
+use super::particle::{Particle, ParticleId};
+
+/// The state of some of a universe's particles, with their metadata and bonds, which can be
+/// restored to undo an edit.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub(super) particles: Vec<(Particle, Option<Metadata>)>,
+    /// The bonds of the particles, by the ids of their ends.
+    pub(super) bonds: Vec<(ParticleId, ParticleId, Bond)>,
+}
+
+impl Snapshot {
+    /// The ids of the particles in the snapshot.
+    pub fn ids(&self) -> impl Iterator<Item = ParticleId> + '_ {
+        self.particles.iter().map(|(particle, _)| particle.id)
+    }
+}
@@ -0,0 +1,26 @@
+use crate::physics::space::Space;
+
+/// Which method `Universe::step` uses to estimate the gravitational field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Derivative)]
+#[derivative(Default)]
+pub enum Solver {
+    /// Barnes-Hut, with a tree that is refitted between rebuilds.
+    #[derivative(Default)]
+    BarnesHut,
+    /// The fast multipole method, with a tree that is rebuilt every step.  The Barnes-Hut tree is
+    /// still refitted for queries of the field.
+    FastMultipole,
+}
+
+/// A method of estimating the net gravitational field at each of a set of bodies.
+pub trait GravitySolver<S: Space> {
+    /// Estimates the net g at each of `positions`, which are the current positions of the bodies
+    /// with the corresponding indices.  `theta` trades accuracy for speed, with zero giving the
+    /// exact direct sum.
+    fn estimate_net_g_all(
+        &self,
+        positions: &[S::Vector],
+        theta: S::Scalar,
+        grav_const: S::Scalar,
+    ) -> Vec<S::Vector>;
+}
@@ -1,9 +1,11 @@
 use std::fmt::Debug;
 use std::ops::{Add, AddAssign, Div, Mul, Sub};
 
-pub trait Space: Default + Copy + Debug {
+pub trait Space: Default + Copy + Debug + Send + Sync {
     type Scalar: Copy
         + Debug
+        + Send
+        + Sync
         + PartialEq
         + PartialOrd
         + Default
@@ -13,6 +15,8 @@ pub trait Space: Default + Copy + Debug {
         + Div<Output = Self::Scalar>;
     type Vector: Copy
         + Debug
+        + Send
+        + Sync
         + PartialEq
         + Default
         + Add<Output = Self::Vector>
@@ -28,13 +32,40 @@ pub trait Space: Default + Copy + Debug {
     const MIN_GRAVITY_DISTANCE_SQUARED: Self::Scalar;
 
     fn magnitude_squared(vector: Self::Vector) -> Self::Scalar;
+    fn magnitude(vector: Self::Vector) -> Self::Scalar;
     fn normalize(vector: Self::Vector) -> Self::Vector;
+
+    /// The sum at `target` of the fields `strength / r^2` towards each of the sources given by
+    /// `positions` and `strengths`, ignoring those within `MIN_GRAVITY_DISTANCE_SQUARED`.  This is
+    /// the inner loop of the tree walk, so implementations should make it easy to vectorise.
+    fn inverse_square_sum(
+        target: Self::Vector,
+        positions: &[Self::Vector],
+        strengths: &[Self::Scalar],
+    ) -> Self::Vector;
 }
 
 pub trait DivisibleSpace<const NUM_SUBDIVISIONS: usize>: Space {
     fn subdivisions_array_default<T: Default>() -> [T; NUM_SUBDIVISIONS];
     fn max_abs_dimension(vector: Self::Vector) -> Self::Scalar;
     fn subdivision_index(pivot: Self::Vector, point: Self::Vector) -> usize;
+    /// Whether `point` lies in the cell of the given width centred on `pivot`, using the same
+    /// boundary convention as `subdivision_index`.
+    fn cell_contains(pivot: Self::Vector, width: Self::Scalar, point: Self::Vector) -> bool;
+    /// The distance from `point` to the furthest corner of the cell of the given width centred on
+    /// `pivot`.
+    fn max_corner_distance(
+        pivot: Self::Vector,
+        width: Self::Scalar,
+        point: Self::Vector,
+    ) -> Self::Scalar;
+    /// The squared distance from `point` to the nearest point of the cell of the given width
+    /// centred on `pivot`, which is zero if the cell contains it.
+    fn cell_distance_squared(
+        pivot: Self::Vector,
+        width: Self::Scalar,
+        point: Self::Vector,
+    ) -> Self::Scalar;
 
     fn subtree_width_pivot(
         i: usize,
@@ -42,3 +73,53 @@ pub trait DivisibleSpace<const NUM_SUBDIVISIONS: usize>: Space {
         pivot: Self::Vector,
     ) -> (Self::Scalar, Self::Vector);
 }
+
+/// A space which can be made periodic, so that the field of each source includes the
+/// contributions of all its periodic images.
+pub trait PeriodicSpace: Space {
+    /// Precomputed corrections to the field of a source's nearest image, accounting for all the
+    /// others.
+    type EwaldTable: Debug + Send + Sync;
+
+    fn ewald_table(box_size: Self::Scalar) -> Self::EwaldTable;
+    /// The shortest offset equivalent to `offset` in a periodic box of the given size.
+    fn nearest_image(offset: Self::Vector, box_size: Self::Scalar) -> Self::Vector;
+    /// The correction to the field of a unit source whose nearest image is at `offset` from the
+    /// target.
+    fn ewald_correction(table: &Self::EwaldTable, offset: Self::Vector) -> Self::Vector;
+}
+
+/// A space with symmetric rank-2 and rank-3 tensor types, used for the quadrupole moments of
+/// multipole expansions and the field derivatives carried by local expansions.
+pub trait TensorSpace: Space {
+    type Tensor: Copy
+        + Debug
+        + Send
+        + Sync
+        + Default
+        + Add<Output = Self::Tensor>
+        + AddAssign<Self::Tensor>
+        + Mul<Self::Scalar, Output = Self::Tensor>;
+    /// A fully symmetric rank-3 tensor.
+    type Tensor3: Copy
+        + Debug
+        + Send
+        + Sync
+        + Default
+        + Add<Output = Self::Tensor3>
+        + AddAssign<Self::Tensor3>;
+    const TENSOR_ZERO: Self::Tensor;
+
+    /// The gradient, with respect to the target position, of the inverse-square field
+    /// `strength * offset / |offset|^3`, where `offset` runs from the target to the source.
+    fn inverse_square_gradient(offset: Self::Vector, strength: Self::Scalar) -> Self::Tensor;
+    /// The second derivatives of the same field with respect to the target position.
+    fn inverse_square_hessian(offset: Self::Vector, strength: Self::Scalar) -> Self::Tensor3;
+    fn transform(tensor: Self::Tensor, vector: Self::Vector) -> Self::Vector;
+    /// The outer product of `vector` with itself.
+    fn outer(vector: Self::Vector) -> Self::Tensor;
+    /// The contraction of `tensor` with `vector` over its last index.
+    fn contract(tensor: Self::Tensor3, vector: Self::Vector) -> Self::Tensor;
+    /// The contraction of `tensor` with `other` over its last two indices.
+    fn contract_tensor(tensor: Self::Tensor3, other: Self::Tensor) -> Self::Vector;
+}
@@ -1,6 +1,13 @@
-use nannou::geom::{pt2, Point2};
+use std::ops::{Add, AddAssign, Mul};
 
-use crate::physics::space::{DivisibleSpace, Space};
+use nannou::geom::{pt2, vec2, Point2};
+
+use crate::physics::ewald::EwaldTable2D;
+use crate::physics::space::{DivisibleSpace, PeriodicSpace, Space, TensorSpace};
+
+/// The number of sources `inverse_square_sum` accumulates side by side, so that the compiler can
+/// keep them in SIMD registers.
+const LANES: usize = 8;
 
 #[derive(Debug, Default, Clone, Copy)]
 pub struct Space2D;
@@ -19,9 +26,42 @@ impl Space for Space2D {
         vector.length_squared()
     }
 
+    fn magnitude(vector: Self::Vector) -> Self::Scalar {
+        vector.length()
+    }
+
     fn normalize(vector: Self::Vector) -> Self::Vector {
         vector.normalize_or_zero()
     }
+
+    fn inverse_square_sum(target: Point2, positions: &[Point2], strengths: &[f32]) -> Point2 {
+        let mut lanes = [Point2::ZERO; LANES];
+        let chunks = positions.chunks_exact(LANES);
+        let (remaining_positions, remaining_strengths) = (
+            chunks.remainder(),
+            &strengths[positions.len() - chunks.remainder().len()..],
+        );
+        for (positions, strengths) in chunks.zip(strengths.chunks_exact(LANES)) {
+            for ((lane, &position), &strength) in lanes.iter_mut().zip(positions).zip(strengths) {
+                *lane += inverse_square(position - target, strength);
+            }
+        }
+        for (&position, &strength) in remaining_positions.iter().zip(remaining_strengths) {
+            lanes[0] += inverse_square(position - target, strength);
+        }
+        lanes.into_iter().fold(Point2::ZERO, |sum, lane| sum + lane)
+    }
+}
+
+/// The field `strength / r^2` towards a source at `offset`, without branches.
+fn inverse_square(offset: Point2, strength: f32) -> Point2 {
+    let distance_squared = offset.length_squared();
+    let inverse_cube = if distance_squared > Space2D::MIN_GRAVITY_DISTANCE_SQUARED {
+        strength / (distance_squared * distance_squared.sqrt())
+    } else {
+        0.0
+    };
+    offset * inverse_cube
 }
 
 impl DivisibleSpace<4> for Space2D {
@@ -42,6 +82,31 @@ impl DivisibleSpace<4> for Space2D {
         }
     }
 
+    fn cell_contains(pivot: Self::Vector, width: Self::Scalar, point: Self::Vector) -> bool {
+        let half_width = width / Self::TWO;
+        let offset = point - pivot;
+        (-half_width..half_width).contains(&offset.x)
+            && (-half_width..half_width).contains(&offset.y)
+    }
+
+    fn max_corner_distance(
+        pivot: Self::Vector,
+        width: Self::Scalar,
+        point: Self::Vector,
+    ) -> Self::Scalar {
+        ((point - pivot).abs() + Point2::splat(width / Self::TWO)).length()
+    }
+
+    fn cell_distance_squared(
+        pivot: Self::Vector,
+        width: Self::Scalar,
+        point: Self::Vector,
+    ) -> Self::Scalar {
+        ((point - pivot).abs() - Point2::splat(width / Self::TWO))
+            .max(Point2::ZERO)
+            .length_squared()
+    }
+
     fn subtree_width_pivot(
         i: usize,
         width: Self::Scalar,
@@ -59,3 +124,150 @@ impl DivisibleSpace<4> for Space2D {
         (width, pivot + pivot_offset)
     }
 }
+
+impl PeriodicSpace for Space2D {
+    type EwaldTable = EwaldTable2D;
+
+    fn ewald_table(box_size: Self::Scalar) -> Self::EwaldTable {
+        EwaldTable2D::new(box_size)
+    }
+
+    fn nearest_image(offset: Self::Vector, box_size: Self::Scalar) -> Self::Vector {
+        offset - (offset / box_size).round() * box_size
+    }
+
+    fn ewald_correction(table: &Self::EwaldTable, offset: Self::Vector) -> Self::Vector {
+        table.correction(offset)
+    }
+}
+
+/// A symmetric 2x2 tensor.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Tensor2D {
+    pub xx: f32,
+    pub xy: f32,
+    pub yy: f32,
+}
+
+impl Add for Tensor2D {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            xx: self.xx + rhs.xx,
+            xy: self.xy + rhs.xy,
+            yy: self.yy + rhs.yy,
+        }
+    }
+}
+
+impl AddAssign for Tensor2D {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Mul<f32> for Tensor2D {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self {
+        Self {
+            xx: self.xx * rhs,
+            xy: self.xy * rhs,
+            yy: self.yy * rhs,
+        }
+    }
+}
+
+/// A fully symmetric 2x2x2 tensor.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Rank3Tensor2D {
+    pub xxx: f32,
+    pub xxy: f32,
+    pub xyy: f32,
+    pub yyy: f32,
+}
+
+impl Add for Rank3Tensor2D {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self {
+            xxx: self.xxx + rhs.xxx,
+            xxy: self.xxy + rhs.xxy,
+            xyy: self.xyy + rhs.xyy,
+            yyy: self.yyy + rhs.yyy,
+        }
+    }
+}
+
+impl AddAssign for Rank3Tensor2D {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl TensorSpace for Space2D {
+    type Tensor = Tensor2D;
+    type Tensor3 = Rank3Tensor2D;
+    const TENSOR_ZERO: Self::Tensor = Tensor2D {
+        xx: 0.0,
+        xy: 0.0,
+        yy: 0.0,
+    };
+
+    fn inverse_square_gradient(offset: Self::Vector, strength: Self::Scalar) -> Self::Tensor {
+        let distance_squared = offset.length_squared();
+        let inverse_cube = strength / (distance_squared * distance_squared.sqrt());
+        let inverse_fifth = 3.0 * inverse_cube / distance_squared;
+        Tensor2D {
+            xx: inverse_fifth * offset.x * offset.x - inverse_cube,
+            xy: inverse_fifth * offset.x * offset.y,
+            yy: inverse_fifth * offset.y * offset.y - inverse_cube,
+        }
+    }
+
+    fn inverse_square_hessian(offset: Self::Vector, strength: Self::Scalar) -> Self::Tensor3 {
+        let distance_squared = offset.length_squared();
+        let inverse_fifth =
+            3.0 * strength / (distance_squared * distance_squared * distance_squared.sqrt());
+        let inverse_seventh = 5.0 * inverse_fifth / distance_squared;
+        let (x, y) = (offset.x, offset.y);
+        Rank3Tensor2D {
+            xxx: inverse_seventh * x * x * x - 3.0 * inverse_fifth * x,
+            xxy: inverse_seventh * x * x * y - inverse_fifth * y,
+            xyy: inverse_seventh * x * y * y - inverse_fifth * x,
+            yyy: inverse_seventh * y * y * y - 3.0 * inverse_fifth * y,
+        }
+    }
+
+    fn transform(tensor: Self::Tensor, vector: Self::Vector) -> Self::Vector {
+        vec2(
+            tensor.xx * vector.x + tensor.xy * vector.y,
+            tensor.xy * vector.x + tensor.yy * vector.y,
+        )
+    }
+
+    fn outer(vector: Self::Vector) -> Self::Tensor {
+        Tensor2D {
+            xx: vector.x * vector.x,
+            xy: vector.x * vector.y,
+            yy: vector.y * vector.y,
+        }
+    }
+
+    fn contract(tensor: Self::Tensor3, vector: Self::Vector) -> Self::Tensor {
+        Tensor2D {
+            xx: tensor.xxx * vector.x + tensor.xxy * vector.y,
+            xy: tensor.xxy * vector.x + tensor.xyy * vector.y,
+            yy: tensor.xyy * vector.x + tensor.yyy * vector.y,
+        }
+    }
+
+    fn contract_tensor(tensor: Self::Tensor3, other: Self::Tensor) -> Self::Vector {
+        vec2(
+            tensor.xxx * other.xx + 2.0 * tensor.xxy * other.xy + tensor.xyy * other.yy,
+            tensor.xxy * other.xx + 2.0 * tensor.xyy * other.xy + tensor.yyy * other.yy,
+        )
+    }
+}
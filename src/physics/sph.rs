@@ -0,0 +1,294 @@
+use std::f32::consts::PI;
+
+use nannou::prelude::*;
+
+use super::barnes_hut::GravityField2D;
+use super::particle::Particle;
+use super::particles::Particles;
+
+/// The number of gas bodies per leaf of the neighbour search tree.
+const NEIGHBOUR_TREE_LEAF_CAPACITY: usize = 8;
+
+/// The settings of the smoothed-particle hydrodynamics applied to gas particles.
+#[derive(Debug, Clone, Copy, Derivative)]
+#[derivative(Default)]
+pub struct Sph {
+    /// The adiabatic index of the gas.
+    #[derivative(Default(value = "5.0 / 3.0"))]
+    pub gamma: f32,
+    /// The number of neighbours each gas particle's smoothing length is chosen to cover.
+    #[derivative(Default(value = "16"))]
+    pub neighbours: usize,
+    /// The strength of the linear term of Monaghan's artificial viscosity.
+    #[derivative(Default(value = "1.0"))]
+    pub viscosity_alpha: f32,
+    /// The strength of the quadratic term, which stops particles interpenetrating in strong
+    /// shocks.
+    #[derivative(Default(value = "2.0"))]
+    pub viscosity_beta: f32,
+}
+
+/// The state of a gas particle needed by its neighbours.
+#[derive(Debug, Clone, Copy)]
+struct Parcel {
+    index: usize,
+    position: Point2,
+    velocity: Vec2,
+    mass: f32,
+    smoothing_length: f32,
+    density: f32,
+    pressure: f32,
+    sound_speed: f32,
+}
+
+impl Sph {
+    /// Updates the density, pressure and rate of change of internal energy of the gas particles,
+    /// returning the hydrodynamic acceleration of every particle, which is zero for those that
+    /// aren't gas.  `tree_width` must be large enough for a tree centred on the origin to cover
+    /// all the particles.
+    pub(super) fn accelerations(&self, particles: &mut Particles, tree_width: f32) -> Vec<Vec2> {
+        let mut accelerations = vec![Vec2::ZERO; particles.len()];
+        let gas: Vec<usize> = (0..particles.len())
+            .filter(|&index| particles.gas(index).is_some())
+            .collect();
+        if gas.is_empty() {
+            return accelerations;
+        }
+
+        let mut tree = GravityField2D::new(tree_width, NEIGHBOUR_TREE_LEAF_CAPACITY);
+        for (local_index, &index) in gas.iter().enumerate() {
+            tree.insert(local_index, particles.point_mass(index));
+        }
+
+        let parcels: Vec<Parcel> = gas
+            .iter()
+            .map(|&index| self.parcel(&particles.get(index), index, &tree, &gas, particles))
+            .collect();
+
+        // A pair interacts if either particle's kernel reaches the other, so add each particle to
+        // the neighbours of those it reaches, in case their own kernels fall short of it.  Then
+        // every pair exerts equal and opposite forces.
+        let mut neighbours: Vec<Vec<usize>> = parcels
+            .iter()
+            .map(|parcel| tree.bodies_within(parcel.position, 2.0 * parcel.smoothing_length))
+            .collect();
+        for i in 0..neighbours.len() {
+            for k in 0..neighbours[i].len() {
+                let j = neighbours[i][k];
+                neighbours[j].push(i);
+            }
+        }
+        for neighbours in &mut neighbours {
+            neighbours.sort_unstable();
+            neighbours.dedup();
+        }
+
+        for (parcel, neighbours) in parcels.iter().zip(neighbours) {
+            let (acceleration, energy_rate) = neighbours
+                .into_iter()
+                .map(|local_index| &parcels[local_index])
+                .filter(|other| other.index != parcel.index)
+                .fold((Vec2::ZERO, 0.0), |(acceleration, energy_rate), other| {
+                    let (dv, du) = self.interaction(parcel, other);
+                    (acceleration + dv, energy_rate + du)
+                });
+
+            accelerations[parcel.index] = acceleration;
+            if let Some(gas) = particles.gas_mut(parcel.index) {
+                gas.density = parcel.density;
+                gas.pressure = parcel.pressure;
+                gas.energy_rate = energy_rate;
+            }
+        }
+        accelerations
+    }
+
+    /// Finds the smoothing length covering the particle's nearest neighbours, and its density,
+    /// pressure and sound speed.
+    fn parcel(
+        &self,
+        particle: &Particle,
+        index: usize,
+        tree: &GravityField2D,
+        gas: &[usize],
+        particles: &Particles,
+    ) -> Parcel {
+        let neighbours: Vec<Particle> = tree
+            .nearest_bodies(particle.position, self.neighbours)
+            .into_iter()
+            .map(|local_index| particles.get(gas[local_index]))
+            .collect();
+        let furthest = neighbours
+            .iter()
+            .map(|other| (other.position - particle.position).length())
+            .fold(0.0f32, f32::max);
+        // Isolated particles still need a finite smoothing length.
+        let smoothing_length = (furthest / 2.0).max(particle.radius);
+
+        let density = neighbours
+            .iter()
+            .map(|other| {
+                other.mass
+                    * kernel(
+                        (other.position - particle.position).length(),
+                        smoothing_length,
+                    )
+            })
+            .sum::<f32>();
+        let internal_energy = particle.gas().map_or(0.0, |gas| gas.internal_energy);
+        let pressure = (self.gamma - 1.0) * density * internal_energy;
+        let sound_speed = if density > 0.0 {
+            (self.gamma * pressure / density).sqrt()
+        } else {
+            0.0
+        };
+
+        Parcel {
+            index,
+            position: particle.position,
+            velocity: particle.velocity,
+            mass: particle.mass,
+            smoothing_length,
+            density,
+            pressure,
+            sound_speed,
+        }
+    }
+
+    /// The acceleration of `parcel` due to `other`, and the resulting rate of change of its
+    /// internal energy.
+    fn interaction(&self, parcel: &Parcel, other: &Parcel) -> (Vec2, f32) {
+        if parcel.density <= 0.0 || other.density <= 0.0 {
+            return (Vec2::ZERO, 0.0);
+        }
+        let offset = parcel.position - other.position;
+        let distance = offset.length();
+        if distance == 0.0 {
+            return (Vec2::ZERO, 0.0);
+        }
+        // Averaging the kernels of the two particles keeps the interaction nearly symmetric.
+        let gradient = offset / distance
+            * (kernel_derivative(distance, parcel.smoothing_length)
+                + kernel_derivative(distance, other.smoothing_length))
+            / 2.0;
+
+        let relative_velocity = parcel.velocity - other.velocity;
+        let viscosity = self.viscosity(parcel, other, offset, relative_velocity);
+        let parcel_term = parcel.pressure / (parcel.density * parcel.density);
+        let other_term = other.pressure / (other.density * other.density);
+
+        let acceleration = -gradient * (other.mass * (parcel_term + other_term + viscosity));
+        let energy_rate =
+            other.mass * (parcel_term + viscosity / 2.0) * relative_velocity.dot(gradient);
+        (acceleration, energy_rate)
+    }
+
+    /// Monaghan's artificial viscosity, which only acts between approaching particles.
+    fn viscosity(
+        &self,
+        parcel: &Parcel,
+        other: &Parcel,
+        offset: Vec2,
+        relative_velocity: Vec2,
+    ) -> f32 {
+        let approach = relative_velocity.dot(offset);
+        if approach >= 0.0 {
+            return 0.0;
+        }
+        let smoothing_length = (parcel.smoothing_length + other.smoothing_length) / 2.0;
+        let mu = smoothing_length * approach
+            / (offset.length_squared() + 0.01 * smoothing_length * smoothing_length);
+        let sound_speed = (parcel.sound_speed + other.sound_speed) / 2.0;
+        let density = (parcel.density + other.density) / 2.0;
+        (-self.viscosity_alpha * sound_speed * mu + self.viscosity_beta * mu * mu) / density
+    }
+}
+
+/// The 2D cubic spline kernel, with support `2 * smoothing_length`.
+fn kernel(distance: f32, smoothing_length: f32) -> f32 {
+    let q = distance / smoothing_length;
+    let sigma = 10.0 / (7.0 * PI * smoothing_length * smoothing_length);
+    if q < 1.0 {
+        sigma * (1.0 - 1.5 * q * q + 0.75 * q * q * q)
+    } else if q < 2.0 {
+        sigma * 0.25 * (2.0 - q).powi(3)
+    } else {
+        0.0
+    }
+}
+
+/// The derivative of `kernel` with respect to distance.
+fn kernel_derivative(distance: f32, smoothing_length: f32) -> f32 {
+    let q = distance / smoothing_length;
+    let sigma = 10.0 / (7.0 * PI * smoothing_length * smoothing_length * smoothing_length);
+    if q < 1.0 {
+        sigma * (-3.0 * q + 2.25 * q * q)
+    } else if q < 2.0 {
+        sigma * -0.75 * (2.0 - q).powi(2)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lattice(n: i32, spacing: f32, internal_energy: f32) -> Particles {
+        (-n..=n)
+            .flat_map(|i| (-n..=n).map(move |j| pt2(i as f32, j as f32) * spacing))
+            .map(|position| Particle::new_gas(position, 1.0, internal_energy))
+            .collect()
+    }
+
+    #[test]
+    fn test_uniform_gas_has_lattice_density_and_no_net_force() {
+        let spacing = 2.0;
+        let mut particles = lattice(10, spacing, 1.0);
+        let accelerations = Sph::default().accelerations(&mut particles, 64.0);
+
+        // The particle at the centre of the lattice is far from its edges.
+        let centre = particles.len() / 2;
+        let density = particles.gas(centre).unwrap().density;
+        let expected = 1.0 / (spacing * spacing);
+        assert!((density - expected).abs() < 0.05 * expected, "{}", density);
+        assert!(
+            accelerations[centre].length() < 1e-4,
+            "{:?}",
+            accelerations[centre]
+        );
+
+        // Pressure pushes the edges outwards.
+        assert!(accelerations[0].x < 0.0 && accelerations[0].y < 0.0);
+    }
+
+    #[test]
+    fn test_momentum_is_conserved_with_varying_smoothing_lengths() {
+        // A converging sunflower whose spacing grows outwards, so the smoothing lengths vary.
+        let mut particles: Particles = (0..200)
+            .map(|i| {
+                let (radius, angle) = (0.05 * (i as f32).powf(1.5), i as f32 * 2.4);
+                let position = pt2(radius * angle.cos(), radius * angle.sin());
+                Particle {
+                    velocity: -position * 0.1,
+                    ..Particle::new_gas(position, 1.0 + (i % 3) as f32, 1.0 + (i % 5) as f32)
+                }
+            })
+            .collect();
+        let accelerations = Sph::default().accelerations(&mut particles, 1024.0);
+
+        let (momentum_rate, scale) = accelerations.iter().zip(particles.masses()).fold(
+            (Vec2::ZERO, 0.0),
+            |(sum, scale), (&acceleration, &mass)| {
+                (
+                    sum + acceleration * mass,
+                    scale + (acceleration * mass).length(),
+                )
+            },
+        );
+        assert!(
+            momentum_rate.length() < 1e-4 * scale,
+            "{momentum_rate:?} of {scale}"
+        );
+    }
+}
@@ -0,0 +1,181 @@
+/// The gravitational constant in simulation units that the default particle distributions are
+/// tuned for.
+const DEFAULT_GRAV_CONST: f64 = 1e2;
+
+/// A system of physical units, with the real gravitational constant expressed in it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Derivative)]
+#[derivative(Default)]
+pub enum UnitSystem {
+    /// The simulation's own arbitrary units.
+    #[derivative(Default)]
+    Simulation,
+    /// Kiloparsecs, solar masses and megayears, for galaxies.
+    Galactic,
+    /// Astronomical units, solar masses and years, for planetary systems.
+    Solar,
+    /// Metres, kilograms and seconds.
+    Si,
+}
+
+impl UnitSystem {
+    pub fn all() -> [Self; 4] {
+        [
+            UnitSystem::Simulation,
+            UnitSystem::Galactic,
+            UnitSystem::Solar,
+            UnitSystem::Si,
+        ]
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            UnitSystem::Simulation => "simulation",
+            UnitSystem::Galactic => "kpc / Msun / Myr",
+            UnitSystem::Solar => "AU / Msun / yr",
+            UnitSystem::Si => "SI",
+        }
+    }
+
+    /// The symbols of the units of length, mass and time.
+    fn symbols(&self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            UnitSystem::Simulation => ("", "", ""),
+            UnitSystem::Galactic => ("kpc", "Msun", "Myr"),
+            UnitSystem::Solar => ("AU", "Msun", "yr"),
+            UnitSystem::Si => ("m", "kg", "s"),
+        }
+    }
+
+    /// The gravitational constant in this system's units.
+    pub fn grav_const(&self) -> f64 {
+        match self {
+            UnitSystem::Simulation => DEFAULT_GRAV_CONST,
+            UnitSystem::Galactic => 4.498_502e-12,
+            UnitSystem::Solar => 4.0 * std::f64::consts::PI * std::f64::consts::PI,
+            UnitSystem::Si => 6.674_30e-11,
+        }
+    }
+
+    /// The speed of light in this system's units, if it has a physical meaning.
+    pub fn speed_of_light(&self) -> Option<f64> {
+        match self {
+            UnitSystem::Simulation => None,
+            UnitSystem::Galactic => Some(306.601_4),
+            UnitSystem::Solar => Some(63_241.08),
+            UnitSystem::Si => Some(299_792_458.0),
+        }
+    }
+}
+
+/// How the simulation's units relate to a physical unit system: a simulation unit of length, mass
+/// or time is `length`, `mass` or `time` of the system's units.  The gravitational constant in
+/// simulation units follows from these.
+#[derive(Debug, Clone, Copy, PartialEq, Derivative)]
+#[derivative(Default)]
+pub struct Units {
+    pub system: UnitSystem,
+    #[derivative(Default(value = "1.0"))]
+    pub length: f64,
+    #[derivative(Default(value = "1.0"))]
+    pub mass: f64,
+    #[derivative(Default(value = "1.0"))]
+    pub time: f64,
+}
+
+impl Units {
+    /// Scales suited to the system, with the unit of time chosen so that the gravitational
+    /// constant is the one the default particle distributions are tuned for.
+    pub fn new(system: UnitSystem) -> Self {
+        let (length, mass): (f64, f64) = match system {
+            UnitSystem::Simulation => (1.0, 1.0),
+            // A parsec and a solar mass.
+            UnitSystem::Galactic => (1e-3, 1.0),
+            // About a Jupiter mass, with distances in tenths of an AU.
+            UnitSystem::Solar => (0.1, 1e-3),
+            UnitSystem::Si => (1.0, 1.0),
+        };
+        let time = (DEFAULT_GRAV_CONST * length.powi(3) / (system.grav_const() * mass)).sqrt();
+        Self {
+            system,
+            length,
+            mass,
+            time,
+        }
+    }
+
+    /// The gravitational constant in simulation units.
+    pub fn grav_const(&self) -> f32 {
+        (self.system.grav_const() * self.mass * self.time * self.time / self.length.powi(3)) as f32
+    }
+
+    /// The speed of light in simulation units, if the system is physical.
+    pub fn speed_of_light(&self) -> Option<f32> {
+        let speed_of_light = self.system.speed_of_light()?;
+        Some((speed_of_light * self.time / self.length) as f32)
+    }
+
+    pub fn length(&self, length: f32) -> String {
+        let (symbol, _, _) = self.system.symbols();
+        format_quantity(length as f64 * self.length, symbol)
+    }
+
+    pub fn mass(&self, mass: f32) -> String {
+        let (_, symbol, _) = self.system.symbols();
+        format_quantity(mass as f64 * self.mass, symbol)
+    }
+
+    pub fn time(&self, time: f32) -> String {
+        let (_, _, symbol) = self.system.symbols();
+        format_quantity(time as f64 * self.time, symbol)
+    }
+
+    pub fn velocity(&self, velocity: f32) -> String {
+        let symbol = match self.system.symbols() {
+            ("", _, _) => String::new(),
+            (length, _, time) => format!("{}/{}", length, time),
+        };
+        format_quantity(velocity as f64 * self.length / self.time, &symbol)
+    }
+
+    pub fn energy(&self, energy: f32) -> String {
+        let symbol = match self.system.symbols() {
+            ("", _, _) => String::new(),
+            ("m", "kg", "s") => "J".to_string(),
+            (length, mass, time) => format!("{} {}^2/{}^2", mass, length, time),
+        };
+        let scale = self.mass * self.length * self.length / (self.time * self.time);
+        format_quantity(energy as f64 * scale, &symbol)
+    }
+}
+
+fn format_quantity(value: f64, symbol: &str) -> String {
+    let number = if value != 0.0 && !(1e-2..1e5).contains(&value.abs()) {
+        format!("{:.3e}", value)
+    } else {
+        format!("{:.3}", value)
+    };
+    if symbol.is_empty() {
+        number
+    } else {
+        format!("{} {}", number, symbol)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    #[test]
+    fn test_earth_orbit_takes_a_year() {
+        let units = Units::new(UnitSystem::Solar);
+        assert!((units.grav_const() - DEFAULT_GRAV_CONST as f32).abs() < 1e-3);
+
+        let (sun_mass, radius) = ((1.0 / units.mass) as f32, (1.0 / units.length) as f32);
+        let speed = (units.grav_const() * sun_mass / radius).sqrt();
+        let period = 2.0 * PI * radius / speed;
+        assert!((period as f64 * units.time - 1.0).abs() < 1e-4);
+        assert_eq!(units.length(radius), "1.000 AU");
+    }
+}
@@ -1,23 +1,108 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
 use nannou::color::Gradient;
 use nannou::prelude::*;
 
 use crate::drawing::{alpha, Drawable};
-use crate::physics::barnes_hut::GravityField2D;
-use crate::physics::point_mass::PointMass;
+use crate::physics::barnes_hut::{Body, ForceStrategy, GravityField2D, Opening, OpeningCriterion};
+use crate::physics::bond::{Bond, Spring};
+use crate::physics::boundary::{Boundary, BoundaryMode};
+use crate::physics::brush::Brush;
+use crate::physics::collision::{self, CollisionMode};
+use crate::physics::cosmology::Cosmology;
+use crate::physics::ewald::PeriodicBox;
+use crate::physics::external::External;
+use crate::physics::fmm::FastMultipole2D;
+use crate::physics::force_law::{ForceLaw, ForceLaw2D};
+use crate::physics::friction::{Drag, DynamicalFriction};
+use crate::physics::metadata::Metadata;
+use crate::physics::post_newtonian::PostNewtonian;
+use crate::physics::region::Region;
+use crate::physics::snapshot::Snapshot;
+use crate::physics::solver::{GravitySolver, Solver};
+use crate::physics::space::PeriodicSpace;
+use crate::physics::space_2d::Space2D;
+use crate::physics::sph::Sph;
+use crate::physics::units::Units;
 use crate::simulation;
+use crate::trails::Trails;
 use crate::view_state::ViewState;
 
-use super::particle::Particle;
+use super::particle::{Particle, ParticleId};
+use super::particles::Particles;
+
+/// The distance within which black holes swallow other particles.
+const BLACK_HOLE_CAPTURE_RADIUS: f32 = 8.0;
 
 #[derive(Debug, Clone, Derivative)]
 #[derivative(Default)]
 pub struct Universe {
-    particles: Vec<Particle>,
+    particles: Particles,
     bounding_boxes: Vec<Rect>,
-    #[derivative(Default(value = "1e3"))]
-    pub black_hole_mass: f32,
     #[derivative(Default(value = "0.7"))]
     pub theta: f32,
+    /// The number of steps between full rebuilds of the gravity field; in between, the field is
+    /// refitted to the particles' new positions.
+    #[derivative(Default(value = "10"))]
+    pub rebuild_interval: usize,
+    /// The number of bodies held by each leaf of the gravity field before it's subdivided.
+    #[derivative(Default(value = "1"))]
+    pub leaf_capacity: usize,
+    pub force_strategy: ForceStrategy,
+    /// How the Barnes-Hut tree walk decides which cells to open.
+    pub opening_criterion: OpeningCriterion,
+    /// Always open cells which contain the target.
+    pub guard_inside_cell: bool,
+    /// The tolerance of the relative opening criterion.
+    #[derivative(Default(value = "0.005"))]
+    pub relative_tolerance: f32,
+    pub solver: Solver,
+    /// The law of the force between the particles.  Laws other than Newtonian gravity always use
+    /// the Barnes-Hut solver.
+    pub force_law: ForceLaw2D,
+    /// The side length of a periodic box centred on the origin, if the universe wraps around.
+    /// Periodic universes always use the Barnes-Hut solver.
+    pub periodic_box: Option<f32>,
+    periodic: Option<PeriodicBox<Space2D>>,
+    pub boundary: Boundary,
+    /// The number, total mass and total momentum of the particles absorbed by the boundary.
+    absorbed_count: usize,
+    absorbed_mass: f32,
+    absorbed_momentum: Vec2,
+    pub collision_mode: CollisionMode,
+    /// The coefficient of restitution for bouncing collisions: one is perfectly elastic.
+    #[derivative(Default(value = "1.0"))]
+    pub restitution: f32,
+    merge_count: usize,
+    /// The number of particles swallowed by black holes.
+    accreted_count: usize,
+    /// Analytic potentials whose gravity is added to that of the particles, outside the force
+    /// law.
+    pub externals: Vec<External>,
+    /// The hydrodynamics of the gas particles.
+    pub sph: Sph,
+    /// The physical units of the simulation, which set the gravitational constant.
+    pub units: Units,
+    /// The simulated time elapsed, in simulation units.
+    age: f32,
+    /// The expanding background, if the particles' positions are comoving coordinates.
+    pub cosmology: Option<Cosmology>,
+    pub drag: Drag,
+    pub dynamical_friction: DynamicalFriction,
+    /// The kinetic energy removed by drag and dynamical friction.
+    dissipated_energy: f32,
+    /// Relativistic corrections to the gravity of black holes.
+    pub post_newtonian: PostNewtonian,
+    bonds: Vec<Bond>,
+    /// The spring used for new bonds.
+    pub spring: Spring,
+    broken_bond_count: usize,
+    next_id: u64,
+    /// The index of each particle, by id.
+    indices: HashMap<ParticleId, usize>,
+    metadata: HashMap<ParticleId, Metadata>,
+    gravity_field: Option<GravityField2D>,
+    steps_since_rebuild: usize,
 }
 
 impl Universe {
@@ -29,80 +114,881 @@ impl Universe {
 }
 
 impl Universe {
-    pub(crate) fn add_moving_particle_at(&mut self, position: Point2) {
-        self.insert(Particle::new_moving(position));
-        println!("Added moving particle at: {:?}", position);
-    }
-}
-
-impl Universe {
-    pub(crate) fn set_black_hole_mass(&mut self, fac: f32) {
-        self.black_hole_mass = fac;
-        info!("Blackhole mass is now: {}", self.black_hole_mass);
+    /// Sets the mass of every black hole, adding one at the origin if there are none.
+    pub(crate) fn set_black_hole_mass(&mut self, mass: f32) {
+        if !(0..self.particles.len()).any(|i| self.particles.capture_radius(i).is_some()) {
+            self.add_black_hole(Point2::ZERO, Vec2::ZERO, mass);
+        }
+        self.update_black_hole_masses(|_| mass);
     }
 }
 
 impl Universe {
     pub(crate) fn multiply_black_hole_mass(&mut self, fac: f32) {
-        self.black_hole_mass *= fac;
-        info!("Blackhole mass is now: {}", self.black_hole_mass);
+        self.update_black_hole_masses(|mass| mass * fac);
     }
 }
 
 impl Universe {
-    pub const G: f32 = 1e2;
     // pub const THETA: f32 = 0.7;
 
     pub fn new(num_particles: usize) -> Self {
         let mut new = Self::default();
+        new.add_black_hole(Point2::ZERO, Vec2::ZERO, 1e3);
         new.add_random_particles(num_particles);
         new
     }
 
     pub fn clear(&mut self) {
         self.particles.clear();
+        self.bonds.clear();
+        self.indices.clear();
+        self.metadata.clear();
+        self.gravity_field = None;
+    }
+
+    /// The gravitational constant in simulation units.
+    pub fn grav_const(&self) -> f32 {
+        self.units.grav_const()
+    }
+
+    pub fn age(&self) -> f32 {
+        self.age
     }
 
     pub fn add_particle_at(&mut self, position: Point2) {
         self.insert(Particle::new(position));
     }
 
+    /// Adds a particle of the given mass and radius moving at `velocity`.
+    pub fn launch_particle_at(&mut self, position: Point2, velocity: Vec2, mass: f32, radius: f32) {
+        self.insert(Particle {
+            velocity,
+            mass,
+            radius,
+            ..Particle::new(position)
+        });
+    }
+
+    /// The path over `steps` steps of `dt` of a particle of `mass` starting at `position` with
+    /// `velocity`, in the field of the particles frozen as of the last step and the external
+    /// potentials.  Any charge is neglected.
+    pub fn predict_trajectory(
+        &self,
+        position: Point2,
+        velocity: Vec2,
+        mass: f32,
+        steps: usize,
+        dt: f32,
+    ) -> Vec<Point2> {
+        let particle = Particle {
+            velocity,
+            mass,
+            ..Particle::new(position)
+        };
+        self.predict(&particle, None, steps, dt)
+    }
+
+    /// The path the particle would follow over `steps` steps of `dt` if it had `velocity`, with
+    /// the others frozen as of the last step.
+    pub fn predict_trajectory_of(
+        &self,
+        index: usize,
+        velocity: Vec2,
+        steps: usize,
+        dt: f32,
+    ) -> Vec<Point2> {
+        let particle = Particle {
+            velocity,
+            ..self.particles.get(index)
+        };
+        self.predict(&particle, Some(index), steps, dt)
+    }
+
+    /// Integrates `particle` through the frozen field, leaving out its own if it's the particle
+    /// with index `own_index`.
+    fn predict(
+        &self,
+        particle: &Particle,
+        own_index: Option<usize>,
+        steps: usize,
+        dt: f32,
+    ) -> Vec<Point2> {
+        let grav_const = self.grav_const();
+        let (gravity_scale, scale_factor) = self.cosmology.map_or((1.0, 1.0), |cosmology| {
+            (cosmology.gravity_scale(), cosmology.scale_factor)
+        });
+        let (mass, strength) = (
+            particle.mass,
+            self.force_law.strength(particle.mass, particle.charge),
+        );
+        self.with_query_field(|field| {
+            let excluded = own_index.and_then(|index| Some((index, field.position_of(index)?)));
+            let acceleration_at = |position: Point2| {
+                let net_g = match excluded {
+                    Some(excluded) => {
+                        field.estimate_net_g_excluding(position, excluded, self.theta, grav_const)
+                    }
+                    None => field.estimate_net_g(position, self.theta, grav_const),
+                };
+                let field = self.force_law.net_field(net_g) * gravity_scale;
+                let acceleration = if mass > 0.0 {
+                    field * (strength / mass)
+                } else {
+                    field
+                };
+                self.externals.iter().fold(acceleration, |sum, external| {
+                    sum + external.g_at(position * scale_factor, grav_const) / scale_factor
+                })
+            };
+            // Leapfrog, which keeps predicted orbits from spiralling in or out.
+            let (mut position, mut velocity) = (particle.position, particle.velocity);
+            let mut acceleration = acceleration_at(position);
+            let mut trajectory = vec![position];
+            for _ in 0..steps {
+                velocity += acceleration * (dt / 2.0);
+                position += velocity * dt;
+                acceleration = acceleration_at(position);
+                velocity += acceleration * (dt / 2.0);
+                trajectory.push(position);
+            }
+            trajectory
+        })
+    }
+
+    /// Paints the brush's particles at `centre`.
+    pub fn paint(&mut self, brush: &Brush, centre: Point2) {
+        let central = if brush.orbit_nearest {
+            self.nearest_massive_body(centre, brush.orbit_min_mass)
+        } else {
+            None
+        };
+        let central = central.map(|index| {
+            let particle = self.particles.get(index);
+            (particle.position, particle.velocity, particle.mass)
+        });
+        for particle in brush.paint(centre, self.grav_const(), central) {
+            self.insert(particle);
+        }
+    }
+
+    /// The index of the nearest particle to `point` of at least `min_mass`.
+    fn nearest_massive_body(&self, point: Point2, min_mass: f32) -> Option<usize> {
+        let distance = |index: usize| (self.particles.positions()[index] - point).length_squared();
+        (0..self.particles.len())
+            .filter(|&index| self.particles.masses()[index] >= min_mass)
+            .min_by(|&a, &b| distance(a).total_cmp(&distance(b)))
+    }
+
     pub fn add_random_particles(&mut self, num_particles: usize) {
         for _ in 0..num_particles {
             self.insert(Particle::new_random());
         }
     }
 
-    pub(super) fn insert(&mut self, particle: Particle) {
+    /// The ids of the black holes.
+    pub fn black_holes(&self) -> Vec<ParticleId> {
+        (0..self.particles.len())
+            .filter(|&index| self.particles.capture_radius(index).is_some())
+            .map(|index| self.id_of(index))
+            .collect()
+    }
+
+    pub fn add_black_hole(&mut self, position: Point2, velocity: Vec2, mass: f32) {
+        self.insert(Particle::new_black_hole(
+            position,
+            velocity,
+            mass,
+            BLACK_HOLE_CAPTURE_RADIUS,
+        ));
+    }
+
+    /// Adds a pair of black holes of equal mass in a circular orbit about `centre`, ignoring the
+    /// pull of everything else.
+    pub fn add_binary_black_hole(&mut self, centre: Point2, separation: f32, mass: f32) {
+        let offset = vec2(separation / 2.0, 0.0);
+        let speed = (self.grav_const() * 2.0 * mass / separation).sqrt() / 2.0;
+        let velocity = vec2(0.0, speed);
+        self.add_black_hole(centre + offset, velocity, mass);
+        self.add_black_hole(centre - offset, -velocity, mass);
+    }
+
+    fn update_black_hole_masses(&mut self, mass: impl Fn(f32) -> f32) {
+        for index in 0..self.particles.len() {
+            let mut particle = self.particles.get(index);
+            if particle.capture_radius().is_some() {
+                particle.mass = mass(particle.mass);
+                info!("Blackhole mass is now: {}", particle.mass);
+                self.particles.set(index, particle);
+            }
+        }
+        // The bodies' strengths in the tree are stale.
+        self.gravity_field = None;
+    }
+
+    /// Adds a neutral plasma of particles with alternating unit charges.
+    pub fn add_random_plasma(&mut self, num_particles: usize) {
+        for i in 0..num_particles {
+            let charge = if i % 2 == 0 { 1.0 } else { -1.0 };
+            self.insert(Particle::new_random_charged(charge));
+        }
+    }
+
+    pub fn add_random_gas(&mut self, num_particles: usize) {
+        for _ in 0..num_particles {
+            self.insert(Particle::new_random_gas());
+        }
+    }
+
+    pub(super) fn insert(&mut self, mut particle: Particle) -> ParticleId {
+        particle.id = ParticleId(self.next_id);
+        self.next_id += 1;
+        self.indices.insert(particle.id, self.particles.len());
         self.particles.push(particle);
+        self.gravity_field = None;
+        particle.id
+    }
+
+    pub fn id_of(&self, index: usize) -> ParticleId {
+        self.particles.ids()[index]
+    }
+
+    /// The current index of the particle, or `None` if it's gone.
+    pub fn index_of(&self, id: ParticleId) -> Option<usize> {
+        self.indices.get(&id).copied()
+    }
+
+    /// Removes the particle, returning whether it was there.
+    pub fn remove(&mut self, id: ParticleId) -> bool {
+        self.remove_all(&[id]) == 1
+    }
+
+    /// Removes the particles, returning how many of them were there.
+    pub fn remove_all(&mut self, ids: &[ParticleId]) -> usize {
+        let mut removed = vec![false; self.particles.len()];
+        let mut count = 0;
+        for index in ids.iter().filter_map(|&id| self.index_of(id)) {
+            count += !removed[index] as usize;
+            removed[index] = true;
+        }
+        if count > 0 {
+            self.remove_particles(&removed);
+        }
+        count
+    }
+
+    /// Removes the particles in `region`, returning how many there were.
+    pub fn remove_in(&mut self, region: Region) -> usize {
+        self.remove_all(&self.ids_in(region))
+    }
+
+    /// The ids of every particle.
+    pub fn ids(&self) -> &[ParticleId] {
+        self.particles.ids()
+    }
+
+    /// The ids of the particles in `region`.
+    pub fn ids_in(&self, region: Region) -> Vec<ParticleId> {
+        let positions = self.particles.positions().iter();
+        positions
+            .zip(self.particles.ids())
+            .filter(|(&position, _)| region.contains(position))
+            .map(|(_, &id)| id)
+            .collect()
+    }
+
+    pub fn scale_masses(&mut self, ids: &[ParticleId], factor: f32) {
+        self.edit(ids, |particle| particle.mass *= factor);
+    }
+
+    pub fn scale_velocities(&mut self, ids: &[ParticleId], factor: f32) {
+        self.edit(ids, |particle| particle.velocity *= factor);
+    }
+
+    /// Adds a bulk velocity to the particles.
+    pub fn add_velocity(&mut self, ids: &[ParticleId], velocity: Vec2) {
+        self.edit(ids, |particle| particle.velocity += velocity);
+    }
+
+    /// The current state of the particles which still exist, for `restore`.
+    pub fn snapshot(&self, ids: &[ParticleId]) -> Snapshot {
+        let indices: Vec<usize> = ids.iter().filter_map(|&id| self.index_of(id)).collect();
+        let particles = indices
+            .iter()
+            .map(|&index| {
+                let particle = self.particles.get(index);
+                (particle, self.metadata.get(&particle.id).cloned())
+            })
+            .collect();
+        let included: HashSet<usize> = indices.iter().copied().collect();
+        let bonds = self
+            .bonds
+            .iter()
+            .filter(|bond| included.contains(&bond.a) || included.contains(&bond.b))
+            .map(|bond| (self.id_of(bond.a), self.id_of(bond.b), *bond))
+            .collect();
+        Snapshot { particles, bonds }
+    }
+
+    /// Changes the particles recorded in `from` as they changed between it and `to`, so that an
+    /// edit recorded as the pair can be undone or redone without rewinding what has happened to
+    /// the particles since.  Those in `to` which are gone are added back whole, with their ids.
+    /// The others are only offset by the differences between the snapshots in position, velocity,
+    /// mass, charge and radius, and only the bonds made or broken between them are broken or made.
+    pub fn apply_change(&mut self, from: &Snapshot, to: &Snapshot) {
+        let recorded: HashMap<ParticleId, &(Particle, Option<Metadata>)> = from
+            .particles
+            .iter()
+            .map(|entry| (entry.0.id, entry))
+            .collect();
+        for (particle, metadata) in &to.particles {
+            let index = match self.index_of(particle.id) {
+                Some(index) => index,
+                None => {
+                    self.indices.insert(particle.id, self.particles.len());
+                    self.particles.push(*particle);
+                    self.set_metadata(particle.id, metadata);
+                    continue;
+                }
+            };
+            match recorded.get(&particle.id) {
+                Some((old, old_metadata)) => {
+                    let current = self.particles.get(index);
+                    self.particles.set(
+                        index,
+                        Particle {
+                            position: current.position + (particle.position - old.position),
+                            velocity: current.velocity + (particle.velocity - old.velocity),
+                            mass: current.mass + (particle.mass - old.mass),
+                            charge: current.charge + (particle.charge - old.charge),
+                            radius: current.radius + (particle.radius - old.radius),
+                            tag: if particle.tag == old.tag {
+                                current.tag
+                            } else {
+                                particle.tag
+                            },
+                            ..current
+                        },
+                    );
+                    if metadata != old_metadata {
+                        self.set_metadata(particle.id, metadata);
+                    }
+                }
+                None => {
+                    self.particles.set(index, *particle);
+                    self.set_metadata(particle.id, metadata);
+                }
+            }
+        }
+
+        let pair = |a: ParticleId, b: ParticleId| (a.min(b), a.max(b));
+        let from_pairs: HashSet<_> = from.bonds.iter().map(|&(a, b, _)| pair(a, b)).collect();
+        let to_pairs: HashSet<_> = to.bonds.iter().map(|&(a, b, _)| pair(a, b)).collect();
+        let ids = self.particles.ids();
+        self.bonds.retain(|bond| {
+            let ends = pair(ids[bond.a], ids[bond.b]);
+            !from_pairs.contains(&ends) || to_pairs.contains(&ends)
+        });
+        let mut bonded: HashSet<(usize, usize)> = self
+            .bonds
+            .iter()
+            .map(|bond| (bond.a.min(bond.b), bond.a.max(bond.b)))
+            .collect();
+        for &(a, b, bond) in &to.bonds {
+            if from_pairs.contains(&pair(a, b)) {
+                continue;
+            }
+            if let (Some(a), Some(b)) = (self.index_of(a), self.index_of(b)) {
+                if bonded.insert((a.min(b), a.max(b))) {
+                    self.bonds.push(Bond { a, b, ..bond });
+                }
+            }
+        }
+        self.gravity_field = None;
+    }
+
+    fn set_metadata(&mut self, id: ParticleId, metadata: &Option<Metadata>) {
+        match metadata {
+            Some(metadata) => self.metadata.insert(id, metadata.clone()),
+            None => self.metadata.remove(&id),
+        };
+    }
+
+    pub fn metadata(&self, id: ParticleId) -> Option<&Metadata> {
+        self.metadata.get(&id)
+    }
+
+    /// The particle's metadata, which is created empty if it has none, or `None` if the particle
+    /// is gone.
+    pub fn metadata_mut(&mut self, id: ParticleId) -> Option<&mut Metadata> {
+        self.indices
+            .contains_key(&id)
+            .then(|| self.metadata.entry(id).or_default())
+    }
+
+    /// Connects two particles with a spring of the current `spring` properties, at rest at their
+    /// current separation.
+    pub fn add_bond(&mut self, a: usize, b: usize) {
+        if a == b || a.max(b) >= self.particles.len() || self.is_bonded(a, b) {
+            return;
+        }
+        let positions = self.particles.positions();
+        let rest_length = separation(
+            &self.boundary,
+            self.periodic_box,
+            positions[a],
+            positions[b],
+        )
+        .length();
+        self.bonds.push(Bond {
+            a,
+            b,
+            rest_length,
+            spring: self.spring,
+        });
+    }
+
+    fn is_bonded(&self, a: usize, b: usize) -> bool {
+        self.bonds
+            .iter()
+            .any(|bond| (bond.a, bond.b) == (a, b) || (bond.a, bond.b) == (b, a))
+    }
+
+    /// The index of the particle under `point`, treating particles as at least `tolerance` in
+    /// radius, as of the last step.
+    pub fn particle_at(&self, point: Point2, tolerance: f32) -> Option<usize> {
+        self.nearest_particles(point, 1).into_iter().find(|&index| {
+            let radius = self.particles.radii()[index];
+            (self.particles.positions()[index] - point).length() <= radius.max(tolerance)
+        })
+    }
+
+    /// The indices of the particles inside `rect`, as of the last step.
+    pub fn particles_in_rect(&self, rect: Rect) -> Vec<usize> {
+        self.with_query_field(|field| field.bodies_in_rect(rect))
+    }
+
+    /// The indices of the `k` particles nearest to `centre`, closest first, as of the last step.
+    pub fn nearest_particles(&self, centre: Point2, k: usize) -> Vec<usize> {
+        self.with_query_field(|field| field.nearest_bodies(centre, k))
+    }
+
+    pub fn position_of(&self, index: usize) -> Point2 {
+        self.particles.positions()[index]
+    }
+
+    pub fn mass_of(&self, index: usize) -> f32 {
+        self.particles.masses()[index]
+    }
+
+    pub fn velocity_of(&self, index: usize) -> Vec2 {
+        self.particles.velocities()[index]
+    }
+
+    /// Applies `edit` to each of the particles which still exist.
+    fn edit(&mut self, ids: &[ParticleId], edit: impl Fn(&mut Particle)) {
+        let indices: Vec<usize> = ids.iter().filter_map(|&id| self.index_of(id)).collect();
+        for index in indices {
+            let mut particle = self.particles.get(index);
+            edit(&mut particle);
+            self.particles.set(index, particle);
+        }
+        // The bodies' strengths in the tree may be stale.
+        self.gravity_field = None;
+    }
+
+    /// Runs a spatial query on the gravity field, building a temporary one if there isn't one.
+    fn with_query_field<T>(&self, query: impl FnOnce(&GravityField2D) -> T) -> T {
+        match &self.gravity_field {
+            Some(gravity_field) => query(gravity_field),
+            None => query(&self.build_gravity_field()),
+        }
     }
 
     fn get_bounding_box(&self) -> Rect {
         self.particles
+            .positions()
             .iter()
-            .fold(Rect::from_w_h(0.0, 0.0), |bounds, particle| {
-                bounds.stretch_to(particle.position)
+            .fold(Rect::from_w_h(0.0, 0.0), |bounds, &position| {
+                bounds.stretch_to(position)
             })
     }
 
-    fn gravity_field(&self) -> GravityField2D {
+    /// The width of a tree centred on the origin which covers all the particles.
+    fn tree_width(&self) -> f32 {
         let bounds = self.get_bounding_box();
         let (l, r, b, t) = bounds.l_r_b_t();
         let max_abs_dimension = l.abs().max(r.abs()).max(b.abs()).max(t.abs());
-        let min_power_2 = at_least!(1.0, max_abs_dimension).log2().ceil() as i32;
-        let width = 2.0f32.powi(min_power_2 + 1);
+        let min_power_2 = at_least!(1.0f32, max_abs_dimension).log2().ceil() as i32;
+        2.0f32.powi(min_power_2 + 1)
+    }
+
+    fn bodies(&self) -> impl Iterator<Item = Body<Space2D>> + '_ {
+        let particles = &self.particles;
+        let charges = particles.masses().iter().zip(particles.charges());
+        charges.enumerate().map(|(index, (&mass, &charge))| {
+            let strength = self.force_law.strength(mass, charge);
+            Body::with_strength(index, particles.point_mass(index), strength)
+        })
+    }
+
+    fn build_gravity_field(&self) -> GravityField2D {
+        let mut gravity_field = GravityField2D::new(self.tree_width(), self.leaf_capacity);
+        gravity_field.law = self.force_law;
+        for body in self.bodies() {
+            gravity_field += body;
+        }
+        gravity_field
+    }
+
+    /// Brings the Ewald corrections up to date with `periodic_box`, and wraps the particles into
+    /// the box.
+    fn update_periodic_box(&mut self) {
+        match self.periodic_box {
+            Some(size) => {
+                if !matches!(&self.periodic, Some(periodic) if periodic.size == size) {
+                    self.periodic = Some(PeriodicBox::new(size));
+                }
+            }
+            None => self.periodic = None,
+        }
+        if let Some(periodic) = &self.periodic {
+            for position in self.particles.positions_mut() {
+                *position = periodic.wrap(*position);
+            }
+        }
+    }
+
+    /// Removes the marked particles and their bonds, renumbering the rest.
+    fn remove_particles(&mut self, removed: &[bool]) {
+        let mut next_index = 0;
+        let new_indices: Vec<Option<usize>> = removed
+            .iter()
+            .map(|&removed| {
+                (!removed).then(|| {
+                    next_index += 1;
+                    next_index - 1
+                })
+            })
+            .collect();
+        self.bonds
+            .retain_mut(|bond| match (new_indices[bond.a], new_indices[bond.b]) {
+                (Some(a), Some(b)) => {
+                    (bond.a, bond.b) = (a, b);
+                    true
+                }
+                _ => false,
+            });
+
+        self.particles.remove_marked(removed);
+        // The remaining particles' indices have changed.
+        self.indices = self
+            .particles
+            .ids()
+            .iter()
+            .enumerate()
+            .map(|(index, &id)| (id, index))
+            .collect();
+        let indices = &self.indices;
+        self.metadata.retain(|id, _| indices.contains_key(id));
+        self.gravity_field = None;
+    }
+
+    /// Applies the boundary to every particle, removing and logging those it absorbs.
+    fn apply_boundary(&mut self) {
+        let boundary = self.boundary;
+        let (positions, velocities) = self.particles.motion_mut();
+        let absorbed: Vec<bool> = positions
+            .iter_mut()
+            .zip(velocities)
+            .map(|(position, velocity)| !boundary.apply(position, velocity))
+            .collect();
+        if !absorbed.contains(&true) {
+            return;
+        }
+
+        let absorbed_particles = self.particles.iter().zip(&absorbed);
+        for (particle, _) in absorbed_particles.filter(|(_, &absorbed)| absorbed) {
+            info!(
+                "Absorbed particle of mass {} at {:?} with velocity {:?}",
+                particle.mass, particle.position, particle.velocity
+            );
+            self.absorbed_count += 1;
+            self.absorbed_mass += particle.mass;
+            self.absorbed_momentum += particle.velocity * particle.mass;
+        }
+        self.remove_particles(&absorbed);
+    }
+
+    /// Merges or bounces colliding particles and lets black holes accrete, using the gravity
+    /// field's tree to find them.  Returns whether the gravity field is still up to date
+    /// afterwards.
+    fn handle_encounters(&mut self) -> bool {
+        let black_holes: Vec<usize> = (0..self.particles.len())
+            .filter(|&i| {
+                self.particles.capture_radius(i).is_some() && self.particles.masses()[i] > 0.0
+            })
+            .collect();
+        if self.collision_mode == CollisionMode::None && black_holes.is_empty() {
+            return false;
+        }
+        self.update_gravity_field();
+
+        let mut removed = vec![false; self.particles.len()];
+        if self.collision_mode != CollisionMode::None {
+            self.handle_collisions(&mut removed);
+        }
+        self.accrete(&black_holes, &mut removed);
+
+        if removed.contains(&true) {
+            self.remove_particles(&removed);
+            false
+        } else {
+            true
+        }
+    }
+
+    /// Finds the particles whose radii overlap, and merges or bounces them, marking those merged
+    /// into others as removed.  Black holes are left to `accrete`.
+    fn handle_collisions(&mut self, removed: &mut [bool]) {
+        let gravity_field = self.gravity_field.as_ref().unwrap();
+        let radii = self.particles.radii();
+        let max_radius = radii
+            .iter()
+            .fold(0.0f32, |max_radius, &radius| max_radius.max(radius));
+        let candidates: Vec<Vec<usize>> = self
+            .particles
+            .positions()
+            .iter()
+            .zip(radii)
+            .map(|(&position, &radius)| gravity_field.bodies_within(position, radius + max_radius))
+            .collect();
+
+        for (i, candidates) in candidates.into_iter().enumerate() {
+            if removed[i] || self.particles.capture_radius(i).is_some() {
+                continue;
+            }
+            for j in candidates {
+                if j <= i || removed[j] || self.particles.capture_radius(j).is_some() {
+                    continue;
+                }
+                let (mut particle, mut other) = (self.particles.get(i), self.particles.get(j));
+                if !collision::overlapping(&particle, &other) {
+                    continue;
+                }
+                match self.collision_mode {
+                    CollisionMode::None => {}
+                    CollisionMode::Merge => {
+                        collision::merge(&mut particle, &other);
+                        removed[j] = true;
+                        self.merge_count += 1;
+                    }
+                    CollisionMode::Bounce => {
+                        collision::bounce(&mut particle, &mut other, self.restitution);
+                        self.particles.set(j, other);
+                    }
+                }
+                self.particles.set(i, particle);
+            }
+        }
+    }
+
+    /// Merges every particle within a black hole's capture radius into it, marking them as
+    /// removed.  Of two black holes within reach of each other, the heavier swallows the lighter.
+    fn accrete(&mut self, black_holes: &[usize], removed: &mut [bool]) {
+        let gravity_field = self.gravity_field.as_ref().unwrap();
+        for &i in black_holes {
+            if removed[i] {
+                continue;
+            }
+            let mut black_hole = self.particles.get(i);
+            let capture_radius = black_hole.capture_radius().unwrap();
+            for j in gravity_field.bodies_within(black_hole.position, capture_radius) {
+                let other = self.particles.get(j);
+                let swallowable = match other.capture_radius() {
+                    Some(_) => {
+                        other.mass < black_hole.mass || (other.mass == black_hole.mass && j > i)
+                    }
+                    None => true,
+                };
+                if j == i || removed[j] || !swallowable {
+                    continue;
+                }
+                collision::merge(&mut black_hole, &other);
+                removed[j] = true;
+                self.accreted_count += 1;
+            }
+            self.particles.set(i, black_hole);
+        }
+    }
 
-        GravityField2D::new(width)
+    /// The accelerations due to drag and dynamical friction, adding the kinetic energy they'll
+    /// remove over the next `dt` to the total dissipated.
+    fn dissipative_accelerations(&mut self, dt: f32, grav_const: f32) -> Vec<Vec2> {
+        let friction = self.dynamical_friction;
+        let particles = &self.particles;
+        let (masses, velocities) = (particles.masses(), particles.velocities());
+        let drag = |index: usize| self.drag.acceleration(velocities[index]);
+        let accelerations: Vec<Vec2> = if masses.iter().any(|&m| friction.applies_to(m)) {
+            self.with_query_field(|field| {
+                (0..particles.len())
+                    .map(|index| {
+                        if !friction.applies_to(masses[index]) {
+                            return drag(index);
+                        }
+                        let particle = particles.get(index);
+                        // The sea of lighter bodies, which excludes the particle itself.
+                        let neighbours: Vec<Particle> = field
+                            .nearest_bodies(particle.position, friction.neighbours)
+                            .into_iter()
+                            .filter(|&other| !friction.applies_to(masses[other]))
+                            .map(|other| particles.get(other))
+                            .collect();
+                        drag(index) + friction.acceleration(&particle, &neighbours, grav_const)
+                    })
+                    .collect()
+            })
+        } else {
+            (0..particles.len()).map(drag).collect()
+        };
+
+        let power: f32 = masses
+            .iter()
+            .zip(velocities)
+            .zip(&accelerations)
+            .map(|((mass, velocity), acceleration)| mass * acceleration.dot(*velocity))
+            .sum();
+        self.dissipated_energy -= power * dt;
+        accelerations
+    }
+
+    /// The accelerations due to the bonds, breaking those stretched or compressed too far.
+    fn bond_accelerations(&mut self) -> Vec<Vec2> {
+        let mut accelerations = vec![Vec2::ZERO; self.particles.len()];
+        let (particles, boundary, periodic_box) =
+            (&self.particles, &self.boundary, self.periodic_box);
+        let bond_count = self.bonds.len();
+        self.bonds.retain(|bond| {
+            let (a, b) = (particles.get(bond.a), particles.get(bond.b));
+            let offset = separation(boundary, periodic_box, a.position, b.position);
+            match bond.force(&a, &b, offset) {
+                Some(force) => {
+                    if a.mass > 0.0 {
+                        accelerations[bond.a] += force / a.mass;
+                    }
+                    if b.mass > 0.0 {
+                        accelerations[bond.b] -= force / b.mass;
+                    }
+                    true
+                }
+                None => false,
+            }
+        });
+        self.broken_bond_count += bond_count - self.bonds.len();
+        accelerations
+    }
+
+    fn build_fast_multipole(&self) -> FastMultipole2D {
+        FastMultipole2D::new(self.tree_width(), self.leaf_capacity, self.bodies())
+    }
+
+    /// Brings the gravity field up to date with the particles' current positions, refitting the
+    /// existing tree where possible and rebuilding it every `rebuild_interval` steps, or whenever
+    /// a particle has left its bounds.
+    fn update_gravity_field(&mut self) {
+        let particles = &self.particles;
+        let refitted = match &mut self.gravity_field {
+            Some(gravity_field)
+                if self.steps_since_rebuild + 1 < self.rebuild_interval
+                    && gravity_field.leaf_capacity() == self.leaf_capacity
+                    && gravity_field.law == self.force_law =>
+            {
+                gravity_field.refit(|index| particles.point_mass(index))
+            }
+            _ => false,
+        };
+
+        if refitted {
+            self.steps_since_rebuild += 1;
+        } else {
+            self.gravity_field = Some(self.build_gravity_field());
+            self.steps_since_rebuild = 0;
+        }
+    }
+}
+
+impl Universe {
+    /// The furthest a particle can move between frames without having wrapped around the boundary
+    /// or the periodic box, beyond which its trail is broken rather than drawn across the box.
+    fn trail_break_distance(&self) -> f32 {
+        let boundary = match self.boundary.mode {
+            BoundaryMode::Wrap => self.boundary.rect().w() / 2.0,
+            _ => f32::INFINITY,
+        };
+        let periodic = self.periodic_box.map_or(f32::INFINITY, |size| size / 2.0);
+        boundary.min(periodic)
+    }
+
+    /// Draws the trails as polylines fading from their particles' colours to transparent.
+    fn draw_trails(&self, draw: &Draw, trails: &Trails, scale: f32) {
+        let max_jump = self.trail_break_distance();
+        for (id, trail) in trails.iter() {
+            let colour = self.metadata.get(&id).and_then(|m| m.colour);
+            let colour = colour.unwrap_or(LIGHTGRAY);
+            for run in trail_runs(trail, max_jump) {
+                if run.len() > 1 {
+                    let points = run.into_iter().map(|(i, position)| {
+                        let opacity = 0.6 * (i + 1) as f32 / trail.len() as f32;
+                        (position, alpha(colour, opacity))
+                    });
+                    draw.polyline()
+                        .stroke_weight(1.0 / scale)
+                        .points_colored(points);
+                }
+            }
+        }
+    }
+}
+
+/// The offset from `from` to `to`, or to its nearest image across the periodic box or a wrapping
+/// boundary.
+fn separation(boundary: &Boundary, periodic_box: Option<f32>, from: Point2, to: Point2) -> Vec2 {
+    let offset = boundary.offset(from, to);
+    match periodic_box {
+        Some(size) => Space2D::nearest_image(offset, size),
+        None => offset,
+    }
+}
+
+/// Splits `trail` into runs of `(index, position)` pairs, breaking it wherever the particle moved
+/// further than `max_jump` between frames.
+fn trail_runs(trail: &VecDeque<Point2>, max_jump: f32) -> Vec<Vec<(usize, Point2)>> {
+    let mut runs = Vec::new();
+    let mut run: Vec<(usize, Point2)> = Vec::with_capacity(trail.len());
+    for (i, &position) in trail.iter().enumerate() {
+        if matches!(run.last(), Some(&(_, last)) if position.distance(last) > max_jump) {
+            runs.push(std::mem::take(&mut run));
+        }
+        run.push((i, position));
     }
+    if !run.is_empty() {
+        runs.push(run);
+    }
+    runs
 }
 
 impl Drawable for Universe {
     fn draw(&self, draw: &Draw, bounds: Rect, view_state: &ViewState) {
+        if view_state.trails.enabled {
+            self.draw_trails(draw, &view_state.trails, view_state.scale);
+        }
         if view_state.draw_particles {
             let gradient = get_gradient();
-            for particle in &self.particles {
+            for particle in self.particles.iter() {
                 if bounds.contains(particle.position) {
-                    particle.draw(draw, view_state, &gradient);
+                    let colour = self.metadata.get(&particle.id).and_then(|m| m.colour);
+                    let colour = colour.map(|colour| alpha(colour, 0.9));
+                    particle.draw(draw, view_state, &gradient, colour);
                 }
             }
         }
@@ -116,6 +1002,40 @@ impl Drawable for Universe {
                     .no_fill();
             });
         }
+        let positions = self.particles.positions();
+        let draw_bond = |start: Point2, end: Point2| {
+            draw.line()
+                .start(start)
+                .end(end)
+                .stroke_weight(1.0 / view_state.scale)
+                .color(alpha(LIGHTGREEN, 0.6));
+        };
+        for bond in &self.bonds {
+            let (a, b) = (positions[bond.a], positions[bond.b]);
+            let offset = separation(&self.boundary, self.periodic_box, a, b);
+            draw_bond(a, a + offset);
+            // A bond across the edge of the box is drawn out from both ends.
+            if offset != b - a {
+                draw_bond(b, b - offset);
+            }
+        }
+        for external in &self.externals {
+            draw.ellipse()
+                .xy(external.position)
+                .radius(5.0 / view_state.scale)
+                .stroke_weight(1.0 / view_state.scale)
+                .stroke_color(alpha(GOLD, 0.6))
+                .no_fill();
+        }
+        if self.boundary.mode != BoundaryMode::None {
+            let rect = self.boundary.rect();
+            draw.rect()
+                .xy(rect.xy())
+                .wh(rect.wh())
+                .stroke_weight(2.0 / view_state.scale)
+                .stroke_color(alpha(LIGHTSTEELBLUE, 0.5))
+                .no_fill();
+        }
     }
 }
 
@@ -125,32 +1045,371 @@ fn get_gradient() -> Gradient<LinSrgba> {
 
 impl simulation::Model for Universe {
     fn step(&mut self, dt: f32) {
-        let mut gravity_field = self.gravity_field();
-
-        for particle in &self.particles {
-            gravity_field += PointMass::new(particle.position, particle.mass);
-        }
+        self.update_periodic_box();
+        let gravity_field_is_current = self.handle_encounters();
+        let grav_const = self.grav_const();
+        let solver = if self.periodic.is_some() || self.force_law != ForceLaw2D::Newtonian {
+            Solver::BarnesHut
+        } else {
+            self.solver
+        };
+        let net_gs = match solver {
+            Solver::BarnesHut => {
+                if !gravity_field_is_current {
+                    self.update_gravity_field();
+                }
+                let gravity_field = self.gravity_field.as_mut().unwrap();
+                gravity_field.strategy = self.force_strategy;
+                gravity_field.opening = Opening {
+                    criterion: self.opening_criterion,
+                    guard_inside_cell: self.guard_inside_cell,
+                    relative_tolerance: self.relative_tolerance,
+                };
+                gravity_field.periodic = self.periodic.clone();
+                gravity_field.set_previous_net_gs(self.particles.accelerations().iter().copied());
+                self.bounding_boxes = gravity_field.get_bounding_boxes();
+                gravity_field.estimate_net_g_all(self.particles.positions(), self.theta, grav_const)
+            }
+            Solver::FastMultipole => {
+                // Keep the Barnes-Hut tree current for queries, which mostly just refits it,
+                // rather than leaving them to build one from scratch on every call.
+                if !gravity_field_is_current {
+                    self.update_gravity_field();
+                }
+                let fast_multipole = self.build_fast_multipole();
+                self.bounding_boxes = fast_multipole.get_bounding_boxes();
+                fast_multipole.estimate_net_g_all(
+                    self.particles.positions(),
+                    self.theta,
+                    grav_const,
+                )
+            }
+        };
 
-        gravity_field += PointMass::new(Point2::new(0.0, 0.0), self.black_hole_mass);
+        // Pressure, bonds, drag and friction have physical length and velocity scales of their own
+        // which don't carry over to comoving coordinates, so an expanding universe goes without.
+        let other_accelerations: Vec<Vec2> = if self.cosmology.is_some() {
+            vec![Vec2::ZERO; self.particles.len()]
+        } else {
+            let tree_width = self.tree_width();
+            let hydro_accelerations = self.sph.accelerations(&mut self.particles, tree_width);
+            let dissipative_accelerations = self.dissipative_accelerations(dt, grav_const);
+            let bond_accelerations = self.bond_accelerations();
+            hydro_accelerations
+                .into_iter()
+                .zip(dissipative_accelerations)
+                .zip(bond_accelerations)
+                .map(|((hydro, dissipative), bond)| hydro + dissipative + bond)
+                .collect()
+        };
 
-        self.bounding_boxes = gravity_field.get_bounding_boxes();
-        let update_particle = |particle: &mut Particle| {
-            let net_g = gravity_field.estimate_net_g(particle.position, self.theta, Self::G);
-            particle.update(dt, net_g);
+        let (gravity_scale, hubble_parameter, scale_factor) = match &self.cosmology {
+            Some(cosmology) => (
+                cosmology.gravity_scale(),
+                cosmology.hubble_parameter(),
+                cosmology.scale_factor,
+            ),
+            None => (1.0, 0.0, 1.0),
+        };
+        let post_newtonian = self.post_newtonian;
+        let black_holes: Vec<Particle> = if post_newtonian.enabled {
+            self.particles
+                .iter()
+                .filter(|particle| particle.capture_radius().is_some())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let force_law = self.force_law;
+        let externals = &self.externals;
+        let particles = &self.particles;
+        let (masses, charges) = (particles.masses(), particles.charges());
+        let (positions, velocities) = (particles.positions(), particles.velocities());
+        let acceleration_of = |index: usize| {
+            let (mass, position, velocity) = (masses[index], positions[index], velocities[index]);
+            let field = force_law.net_field(net_gs[index]) * gravity_scale;
+            let strength = force_law.strength(mass, charges[index]);
+            let acceleration = if mass > 0.0 {
+                field * (strength / mass)
+            } else {
+                field
+            };
+            // The external potentials and post-Newtonian terms act on physical positions and
+            // velocities, `a x` and `a (v + H x)`, and a physical acceleration is `a` times the
+            // comoving one.
+            let external_g = externals.iter().fold(Vec2::ZERO, |sum, external| {
+                sum + external.g_at(position * scale_factor, grav_const) / scale_factor
+            });
+            let relativistic = black_holes.iter().fold(Vec2::ZERO, |sum, black_hole| {
+                let offset = position - black_hole.position;
+                let relative_velocity = velocity - black_hole.velocity + offset * hubble_parameter;
+                sum + post_newtonian.acceleration(
+                    offset * scale_factor,
+                    relative_velocity * scale_factor,
+                    mass,
+                    black_hole.mass,
+                    grav_const,
+                ) / scale_factor
+            });
+            let hubble_drag = velocity * (-2.0 * hubble_parameter);
+            acceleration + external_g + other_accelerations[index] + relativistic + hubble_drag
         };
 
         #[cfg(feature = "rayon")]
-        {
+        let accelerations: Vec<Vec2> = {
             use rayon::prelude::*;
-            self.particles.par_iter_mut().for_each(update_particle);
-        }
+            (0..particles.len())
+                .into_par_iter()
+                .map(acceleration_of)
+                .collect()
+        };
         #[cfg(not(feature = "rayon"))]
-        {
-            self.particles.iter_mut().for_each(update_particle);
+        let accelerations: Vec<Vec2> = (0..particles.len()).map(acceleration_of).collect();
+        self.particles.integrate(dt, &accelerations);
+
+        self.apply_boundary();
+        self.age += dt;
+        if let Some(cosmology) = &mut self.cosmology {
+            cosmology.advance(dt);
         }
     }
 
+    fn redshift(&self) -> Option<f32> {
+        self.cosmology.map(|cosmology| cosmology.redshift())
+    }
+
     fn stats_string(&self) -> String {
-        format!("p:{:6} ", self.particles.len())
+        let mut stats = format!(
+            "p:{:6} t:{} ",
+            self.particles.len(),
+            self.units.time(self.age)
+        );
+        if self.merge_count > 0 {
+            stats += &format!("merges:{:6} ", self.merge_count);
+        }
+        if self.accreted_count > 0 {
+            stats += &format!("accreted:{:6} ", self.accreted_count);
+        }
+        if self.broken_bond_count > 0 {
+            stats += &format!("broken bonds:{:6} ", self.broken_bond_count);
+        }
+        if self.dissipated_energy != 0.0 {
+            stats += &format!("dissipated:{} ", self.units.energy(self.dissipated_energy));
+        }
+        if self.absorbed_count > 0 {
+            stats += &format!(
+                "absorbed:{:6} (m:{:.1}, p:({:.1}, {:.1})) ",
+                self.absorbed_count,
+                self.absorbed_mass,
+                self.absorbed_momentum.x,
+                self.absorbed_momentum.y
+            );
+        }
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bonds_span_periodic_edges() {
+        let periodic = Universe {
+            periodic_box: Some(100.0),
+            ..Universe::default()
+        };
+        let wrapping = Universe {
+            boundary: Boundary {
+                mode: BoundaryMode::Wrap,
+                size: 100.0,
+            },
+            ..Universe::default()
+        };
+        for mut universe in [periodic, wrapping] {
+            universe.add_particle_at(pt2(-48.0, 0.0));
+            universe.add_particle_at(pt2(47.0, 0.0));
+            universe.add_bond(0, 1);
+            assert_eq!(universe.bonds[0].rest_length, 5.0);
+
+            universe.particles.positions_mut()[1] = pt2(47.0, 3.0);
+            let accelerations = universe.bond_accelerations();
+            assert_eq!(universe.bonds.len(), 1);
+            // Stretched across the edge, the bond pulls each end towards the other's image.
+            let towards_image = vec2(-5.0, 3.0).normalize();
+            assert!(accelerations[0].normalize().distance(towards_image) < 1e-4);
+            assert!(accelerations[1].normalize().distance(-towards_image) < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_expanding_universe_converts_externals_and_drops_bonds() {
+        use crate::physics::external::Potential;
+        use crate::simulation::Model;
+
+        // Half its present size, and without a Hubble drag.
+        let cosmology = Cosmology {
+            hubble_constant: 0.0,
+            ..Cosmology::starting_at(1.0)
+        };
+        let mut universe = Universe {
+            cosmology: Some(cosmology),
+            externals: vec![External::new(
+                Point2::ZERO,
+                Potential::PointMass { mass: 1e3 },
+            )],
+            ..Universe::default()
+        };
+        universe.add_particle_at(pt2(100.0, 0.0));
+        // A massless pair, bonded and then stretched far beyond breaking.
+        for y in [300.0, 310.0] {
+            universe.insert(Particle {
+                mass: 0.0,
+                ..Particle::new(pt2(0.0, y))
+            });
+        }
+        universe.add_bond(1, 2);
+        universe.particles.positions_mut()[2] = pt2(0.0, 400.0);
+
+        let dt = 1e-3;
+        universe.step(dt);
+        // The point mass is 50 away physically, and a comoving acceleration is twice a physical one.
+        let physical_g = universe.grav_const() * 1e3 / (50.0 * 50.0);
+        let expected = vec2(-physical_g * 2.0 * dt, 0.0);
+        let velocity = universe.velocity_of(0);
+        assert!(
+            velocity.distance(expected) < 0.05 * expected.length(),
+            "{velocity:?}"
+        );
+        assert_eq!(universe.bonds.len(), 1);
+    }
+
+    #[test]
+    fn test_trails_break_across_periodic_box() {
+        let universe = Universe {
+            periodic_box: Some(100.0),
+            ..Universe::default()
+        };
+        let trail = VecDeque::from([
+            pt2(40.0, 0.0),
+            pt2(48.0, 0.0),
+            pt2(-44.0, 0.0),
+            pt2(-36.0, 0.0),
+        ]);
+
+        let runs = trail_runs(&trail, universe.trail_break_distance());
+        assert_eq!(
+            runs,
+            [
+                vec![(0, pt2(40.0, 0.0)), (1, pt2(48.0, 0.0))],
+                vec![(2, pt2(-44.0, 0.0)), (3, pt2(-36.0, 0.0))],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ids_survive_removal() {
+        let mut universe = Universe::default();
+        let ids: Vec<ParticleId> = (0..4)
+            .map(|i| universe.insert(Particle::new(pt2(i as f32 * 100.0, 0.0))))
+            .collect();
+        universe.metadata_mut(ids[3]).unwrap().name = "last".to_string();
+        universe.add_bond(2, 3);
+
+        assert!(universe.remove(ids[1]));
+        assert!(!universe.remove(ids[1]));
+        assert_eq!(universe.index_of(ids[1]), None);
+        assert!(universe.metadata_mut(ids[1]).is_none());
+
+        let index = universe.index_of(ids[3]).unwrap();
+        assert_eq!(index, 2);
+        assert_eq!(universe.position_of(index), pt2(300.0, 0.0));
+        assert_eq!(universe.metadata(ids[3]).unwrap().name, "last");
+        assert_eq!((universe.bonds[0].a, universe.bonds[0].b), (1, 2));
+
+        universe.remove(ids[2]);
+        assert!(universe.bonds.is_empty());
+    }
+
+    #[test]
+    fn test_predicted_circular_orbit() {
+        let mut universe = Universe::default();
+        universe.add_black_hole(Point2::ZERO, Vec2::ZERO, 1e3);
+        let (radius, mass) = (100.0, 1e3);
+        let speed = (universe.grav_const() * mass / radius).sqrt();
+        let half_period = PI * radius / speed;
+        let steps = 2000;
+
+        let trajectory = universe.predict_trajectory(
+            pt2(radius, 0.0),
+            vec2(0.0, speed),
+            1.0,
+            steps,
+            half_period / steps as f32,
+        );
+        assert_eq!(trajectory.len(), steps + 1);
+        let end = trajectory[steps];
+        assert!((end - pt2(-radius, 0.0)).length() < 1.0, "{:?}", end);
+
+        // A particle's own mass, frozen where it starts, doesn't pull it back.
+        universe.launch_particle_at(pt2(radius, 0.0), Vec2::ZERO, 1e3, 5.0);
+        let index = universe.ids().len() - 1;
+        let trajectory = universe.predict_trajectory_of(
+            index,
+            vec2(0.0, speed),
+            steps,
+            half_period / steps as f32,
+        );
+        let end = trajectory[steps];
+        assert!((end - pt2(-radius, 0.0)).length() < 1.0, "{:?}", end);
+    }
+
+    #[test]
+    fn test_restore_undoes_edits() {
+        let mut universe = Universe::default();
+        for i in 0..5 {
+            universe.insert(Particle::new(pt2(i as f32 * 100.0, 0.0)));
+        }
+        universe.add_bond(0, 1);
+        universe.add_bond(1, 4);
+        let region = Region::Circle {
+            centre: pt2(50.0, 0.0),
+            radius: 60.0,
+        };
+        let selected = universe.ids_in(region);
+        assert_eq!(selected.len(), 2);
+        universe.metadata_mut(selected[0]).unwrap().name = "first".to_string();
+
+        let before = universe.snapshot(&selected);
+        universe.add_velocity(&selected, vec2(1.0, 0.0));
+        universe.scale_velocities(&selected, 2.0);
+        assert_eq!(universe.velocity_of(1), vec2(2.0, 0.0));
+        assert_eq!(universe.remove_in(region), 2);
+        assert!(universe.bonds.is_empty());
+
+        universe.apply_change(&universe.snapshot(&selected), &before);
+        assert_eq!(universe.ids().len(), 5);
+        let index = universe.index_of(selected[1]).unwrap();
+        assert_eq!(universe.position_of(index), pt2(100.0, 0.0));
+        assert_eq!(universe.velocity_of(index), Vec2::ZERO);
+        assert_eq!(universe.metadata(selected[0]).unwrap().name, "first");
+        assert_eq!(universe.bonds.len(), 2);
+
+        // Bonds made since the snapshot are undone too.
+        let ends = [universe.id_of(2), universe.id_of(3)];
+        let unbonded = universe.snapshot(&ends);
+        universe.add_bond(2, 3);
+        universe.apply_change(&universe.snapshot(&ends), &unbonded);
+        assert_eq!(universe.bonds.len(), 2);
+
+        // Changes only undo what they changed, leaving the particles' motion since alone.
+        let before = universe.snapshot(&ends);
+        universe.add_velocity(&ends, vec2(0.0, 3.0));
+        let after = universe.snapshot(&ends);
+        let moved = universe.position_of(2) + vec2(5.0, 0.0);
+        universe.particles.positions_mut()[2] = moved;
+        universe.apply_change(&after, &before);
+        assert_eq!(universe.position_of(2), moved);
+        assert_eq!(universe.velocity_of(2), Vec2::ZERO);
     }
 }
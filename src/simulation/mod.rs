@@ -9,6 +9,10 @@ pub trait Model: Sized {
     fn stats_string(&self) -> String {
         "".to_string()
     }
+    /// The redshift of the model's expanding background, if it has one.
+    fn redshift(&self) -> Option<f32> {
+        None
+    }
 }
 
 #[derive(Debug, Default)]
@@ -74,8 +78,11 @@ impl<M: Model> Simulation<M> {
         self.stats.end_update();
 
         static_rate_limit!(secs = 1, {
-            self.stats
-                .log(self.stats_last_logged, self.model.stats_string().as_str());
+            self.stats.log(
+                self.stats_last_logged,
+                self.model.stats_string().as_str(),
+                self.model.redshift(),
+            );
             self.stats_last_logged = self.stats;
         });
     }
@@ -55,7 +55,7 @@ impl Stats {
         }
     }
 
-    pub fn log(&self, last_logged: Self, model_stats: &str) {
+    pub fn log(&self, last_logged: Self, model_stats: &str, redshift: Option<f32>) {
         let delta = self.relative_to(last_logged);
 
         let steps = self.steps;
@@ -68,8 +68,9 @@ impl Stats {
         let work_per_step = Duration::from_secs_f32(delta.mean_work_per_step().unwrap_or(0.0));
         let fps = delta.frames as f32 / delta.real_age;
         let hz = delta.steps as f32 / delta.real_age;
+        let redshift = redshift.map_or(String::new(), |z| format!(" (z = {z:.3})"));
         info!(target:"barnes_hut::sim",
-            "step {steps:6} {model_stats}simulated {sim_time:>7.1?} in {real_time:>6.1?} ({sim_percent:3.0}%), \
+            "step {steps:6} {model_stats}simulated {sim_time:>7.1?}{redshift} in {real_time:>6.1?} ({sim_percent:3.0}%), \
              lag{d_lag_ms:>+6.3?}ms, \
              spent:{work_per_step:>9.3?}/step {fps:3.0}FPS, {hz:3.0}Hz"
         );
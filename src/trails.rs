@@ -0,0 +1,95 @@
+use std::collections::{HashMap, VecDeque};
+
+use nannou::prelude::*;
+
+use crate::physics::ParticleId;
+
+/// Which particles leave trails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Derivative)]
+#[derivative(Default)]
+pub enum TrailFilter {
+    #[derivative(Default)]
+    All,
+    /// Only the selected particles.
+    Selected,
+    /// Only the particles in a group.
+    Grouped,
+}
+
+/// The recent positions of particles, kept to draw fading trails behind them.
+#[derive(Debug, Clone, Derivative)]
+#[derivative(Default)]
+pub struct Trails {
+    pub enabled: bool,
+    /// The number of positions kept for each particle, one per frame.
+    #[derivative(Default(value = "60"))]
+    pub length: usize,
+    pub filter: TrailFilter,
+    trails: HashMap<ParticleId, Trail>,
+    /// The number of times positions have been recorded, to tell which trails are stale.
+    frame: u64,
+}
+
+#[derive(Debug, Clone)]
+struct Trail {
+    positions: VecDeque<Point2>,
+    last_recorded: u64,
+}
+
+impl Trails {
+    /// Appends the current positions of the particles which leave trails, and forgets the trails
+    /// of any others.
+    pub fn record(&mut self, positions: impl IntoIterator<Item = (ParticleId, Point2)>) {
+        if !self.enabled {
+            self.trails.clear();
+            return;
+        }
+        self.frame += 1;
+        let (frame, length) = (self.frame, self.length);
+        for (id, position) in positions {
+            let trail = self.trails.entry(id).or_insert_with(|| Trail {
+                positions: VecDeque::with_capacity(length + 1),
+                last_recorded: frame,
+            });
+            trail.positions.push_back(position);
+            while trail.positions.len() > length {
+                trail.positions.pop_front();
+            }
+            trail.last_recorded = frame;
+        }
+        self.trails.retain(|_, trail| trail.last_recorded == frame);
+    }
+
+    /// Each particle's trail, from its oldest position to its newest.
+    pub fn iter(&self) -> impl Iterator<Item = (ParticleId, &VecDeque<Point2>)> {
+        self.trails
+            .iter()
+            .map(|(&id, trail)| (id, &trail.positions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trails_are_bounded() {
+        let mut trails = Trails {
+            enabled: true,
+            length: 3,
+            ..Trails::default()
+        };
+        let (a, b) = (ParticleId::for_test(1), ParticleId::for_test(2));
+        for x in 0..5 {
+            let x = x as f32;
+            trails.record([(a, pt2(x, 0.0)), (b, pt2(0.0, x))]);
+        }
+        trails.record([(a, pt2(5.0, 0.0))]);
+
+        let recorded: Vec<_> = trails.iter().collect();
+        assert_eq!(recorded.len(), 1);
+        let (id, trail) = recorded[0];
+        assert_eq!(id, a);
+        assert_eq!(*trail, [pt2(3.0, 0.0), pt2(4.0, 0.0), pt2(5.0, 0.0)]);
+    }
+}
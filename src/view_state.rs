@@ -1,6 +1,8 @@
 use nannou::prelude::*;
 
-#[derive(Debug, Clone, Copy, Derivative)]
+use crate::trails::Trails;
+
+#[derive(Debug, Clone, Derivative)]
 #[derivative(Default)]
 pub struct ViewState {
     /// The bounds of the inspector window in app coordinates.
@@ -11,6 +13,8 @@ pub struct ViewState {
 
     pub draw_quad_tree: bool,
 
+    pub trails: Trails,
+
     pub pan: Point2,
 
     #[derivative(Default(value = "1.0"))]